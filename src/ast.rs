@@ -1,10 +1,24 @@
-use std::ops::Deref;
-use crate::token::Token;
+use std::ops::{Deref, DerefMut};
+use crate::token::{Span, Spanned, Token};
+
+/// Builtin integer types, usable as literal suffixes like `10u8`.
+pub const INTEGER_TYPES: [&str; 9] = ["i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64", "usize"];
+
+/// Builtin floating-point types, usable as literal suffixes like `1.5f32`.
+pub const FLOAT_TYPES: [&str; 2] = ["f32", "f64"];
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ValueType {
     Type(String),
-    Func(FuncDecl)
+    Func(FuncDecl),
+    /// `[T; N]`, a fixed number of values stored inline.
+    Array(Box<ValueType>, u64),
+    /// `[]T`, a read-only view of a run of values stored elsewhere.
+    Slice(Box<ValueType>),
+    /// `*T`, the address of a value stored elsewhere.
+    Pointer(Box<ValueType>),
+    /// Not inferred yet, or the type of an expression that failed to check.
+    Unknown,
 }
 
 impl ValueType {
@@ -15,12 +29,90 @@ impl ValueType {
     pub fn str() -> Self {
         Self::Type("str".to_owned())
     }
+
+    pub fn void() -> Self {
+        Self::Type("void".to_owned())
+    }
+
+    pub fn bool() -> Self {
+        Self::Type("bool".to_owned())
+    }
+
+    pub fn usize() -> Self {
+        Self::Type("usize".to_owned())
+    }
+
+    pub fn f64() -> Self {
+        Self::Type("f64".to_owned())
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || self.is_float()
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Self::Type(name) if FLOAT_TYPES.contains(&name.as_str()))
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.int_range().is_some()
+    }
+
+    pub fn is_signed(&self) -> bool {
+        self.int_range().is_some_and(|(min, _)| min < 0)
+    }
+
+    /// Strips any array types, returning the type of the innermost elements.
+    pub fn element_base(&self) -> &ValueType {
+        match self {
+            Self::Array(element, _) => element.element_base(),
+            vtype => vtype,
+        }
+    }
+
+    /// Returns the smallest and largest value of an integer type.
+    pub fn int_range(&self) -> Option<(i128, i128)> {
+        let Self::Type(name) = self else { return None };
+
+        Some(match name.as_str() {
+            "i8" => (i8::MIN.into(), i8::MAX.into()),
+            "i16" => (i16::MIN.into(), i16::MAX.into()),
+            "i32" => (i32::MIN.into(), i32::MAX.into()),
+            "i64" => (i64::MIN.into(), i64::MAX.into()),
+            "u8" => (0, u8::MAX.into()),
+            "u16" => (0, u16::MAX.into()),
+            "u32" => (0, u32::MAX.into()),
+            "u64" | "usize" => (0, u64::MAX.into()),
+            _ => return None,
+        })
+    }
+}
+
+impl std::fmt::Display for ValueType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueType::Type(name) => write!(f, "{name}"),
+            ValueType::Func(decl) => write!(f, "fn({}) -> {}",
+                decl.params.iter()
+                    .map(|param| param.vtype.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                decl.vtype),
+            ValueType::Array(element, len) => write!(f, "[{element}; {len}]"),
+            ValueType::Slice(element) => write!(f, "[]{element}"),
+            ValueType::Pointer(pointee) => write!(f, "*{pointee}"),
+            ValueType::Unknown => write!(f, "{{unknown}}"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Symbol {
     pub name: String,
     pub vtype: ValueType,
+    pub span: Span,
+    /// Declared with `mut`, so it may be assigned after initialization.
+    pub mutable: bool,
 }
 
 #[derive(Debug, PartialEq)]
@@ -28,12 +120,23 @@ pub enum ExprKind {
     Ident {
         value: String,
     },
+    /// An integer literal. Negative literals are a `Unary` minus applied to
+    /// one of these.
     Int {
-        value: i32,
+        value: u64,
+        suffix: Option<ValueType>,
+    },
+    /// A floating-point literal, negated the same way as `Int`.
+    Float {
+        value: f64,
+        suffix: Option<ValueType>,
     },
     String {
         value: String,
     },
+    Bool {
+        value: bool,
+    },
     Unary {
         op: Token,
         right: Box<Expression>,
@@ -47,12 +150,63 @@ pub enum ExprKind {
         func: Box<Expression>,
         args: Vec<Expression>,
     },
+    /// `target = value`, or with `op` set, a compound assignment like
+    /// `target += value`.
+    Assign {
+        op: Option<Token>,
+        target: Box<Expression>,
+        value: Box<Expression>,
+    },
+    /// `Name { field: value, ... }`, with fields in the order written.
+    StructLit {
+        name: Spanned<String>,
+        fields: Vec<(Spanned<String>, Expression)>,
+    },
+    /// `Enum::Variant(args)`, or `Enum::Variant` for a variant without payload.
+    Variant {
+        enum_name: Spanned<String>,
+        variant: Spanned<String>,
+        args: Vec<Expression>,
+    },
+    /// `[a, b, c]`
+    Array {
+        elements: Vec<Expression>,
+    },
+    /// `value[index]`
+    Index {
+        value: Box<Expression>,
+        index: Box<Expression>,
+    },
+    /// `value[start..end]`, where either bound may be left out.
+    Slice {
+        value: Box<Expression>,
+        start: Option<Box<Expression>>,
+        end: Option<Box<Expression>>,
+    },
+    /// `value.field`
+    Field {
+        value: Box<Expression>,
+        field: Spanned<String>,
+    },
+    /// `value as vtype`, an explicit conversion between numeric types.
+    Cast {
+        value: Box<Expression>,
+        vtype: Spanned<ValueType>,
+    },
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Expression {
     pub kind: ExprKind,
     pub vtype: ValueType,
+    pub span: Span,
+}
+
+impl Expression {
+    /// Creates an expression whose type is filled in later by the type checker.
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, vtype: ValueType::Unknown, span }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -66,9 +220,9 @@ impl Deref for BlockStmt {
     }
 }
 
-impl From<BlockStmt> for Statement {
-    fn from(value: BlockStmt) -> Self {
-        Statement::Block { body: value }
+impl DerefMut for BlockStmt {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
     }
 }
 
@@ -76,17 +230,96 @@ impl From<BlockStmt> for Statement {
 pub struct FuncDecl {
     pub name: String,
     pub vtype: Box<ValueType>,
-    pub params: Vec<Symbol>
+    pub params: Vec<Symbol>,
+    pub span: Span,
+}
+
+impl FuncDecl {
+    /// Whether this is the builtin function `name`, rather than a function
+    /// of the program.
+    pub fn is_builtin(&self, name: &str) -> bool {
+        self.span.line == 0 && self.name == name
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructDecl {
+    pub name: String,
+    pub fields: Vec<Symbol>,
+    pub span: Span,
+}
+
+impl StructDecl {
+    pub fn field(&self, name: &str) -> Option<&Symbol> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub name: String,
+    pub fields: Vec<ValueType>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumDecl {
+    pub name: String,
+    pub variants: Vec<Variant>,
+    pub span: Span,
+}
+
+impl EnumDecl {
+    pub fn variant(&self, name: &str) -> Option<&Variant> {
+        self.variants.iter().find(|variant| variant.name == name)
+    }
 }
 
 #[derive(Debug, PartialEq)]
-pub enum Statement {
-    Let { name: String, vtype: String, value: Option<Expression> },
+pub enum Pattern {
+    /// `Enum::Variant(a, b)`, binding the payload to the given names. A
+    /// binding named `_` is ignored. Their types are filled in by the type
+    /// checker.
+    Variant {
+        enum_name: Spanned<String>,
+        variant: Spanned<String>,
+        bindings: Vec<Symbol>,
+    },
+    /// `_`, matching anything.
+    Wildcard,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MatchArm {
+    pub pattern: Spanned<Pattern>,
+    pub body: BlockStmt,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StmtKind {
+    /// `vtype` is `Unknown` until inferred when the annotation is left out.
+    Let { name: Spanned<String>, mutable: bool, vtype: Spanned<ValueType>, value: Option<Expression> },
     Return { value: Expression },
-    If { cond: Expression, then: BlockStmt, else_then: Option<BlockStmt> },
+    /// `else_then` is either a `Block` or, for `else if` chains, another `If`.
+    If { cond: Expression, then: BlockStmt, else_then: Option<Box<Statement>> },
+    While { cond: Expression, body: BlockStmt },
+    /// `for var in start..end`, counting up with `end` excluded.
+    For { var: Spanned<String>, start: Expression, end: Expression, body: BlockStmt },
+    Break,
+    Continue,
     Expression { value: Expression },
     Block { body: BlockStmt },
     Func { decl: FuncDecl, body: BlockStmt },
+    Struct { decl: StructDecl },
+    Enum { decl: EnumDecl },
+    /// Runs the first arm whose pattern matches `value`.
+    Match { value: Expression, arms: Vec<MatchArm> },
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Statement {
+    pub kind: StmtKind,
+    pub span: Span,
 }
 
 pub struct Program {
@@ -1,62 +1,610 @@
-use crate::ast::{BlockStmt, ExprKind, Expression, FuncDecl, Program, Statement, Symbol, ValueType};
+use std::{cell::{Cell, RefCell}, collections::HashSet};
 
-pub struct Compiler {
+use crate::{ast::{BlockStmt, EnumDecl, ExprKind, Expression, FuncDecl, MatchArm, Pattern, Program, Statement, StmtKind, StructDecl, Symbol, ValueType}, token::Token};
+
+/// C precedence of assignments, which group to the right.
+const ASSIGN: u8 = 2;
+/// C precedence of prefix operators and casts.
+const UNARY: u8 = 14;
+/// C precedence of calls and of anything that never needs parentheses.
+const POSTFIX: u8 = 15;
+
+/// Gets memory for `alloc`, aborting when there is none left.
+const ALLOCATOR: &str = r#"static void* sxl_malloc(size_t size, const char* loc) {
+    void* ptr = malloc(size);
+    if (!ptr) {
+        fflush(stdout);
+        fprintf(stderr, "%s: out of memory\n", loc);
+        abort();
+    }
+    return ptr;
+}
+"#;
+
+/// Gets memory for `alloc` and frees it, keeping track of every allocation
+/// to report leaks at exit and abort on double or invalid frees. Freed memory
+/// is never reused, so that a stale pointer can't match a new allocation.
+const DEBUG_ALLOCATOR: &str = r#"typedef struct sxl_allocation {
+    void* ptr;
+    size_t size;
+    const char* loc;
+    const char* freed_at;
+    struct sxl_allocation* next;
+} sxl_allocation;
+
+static sxl_allocation* sxl_allocations = NULL;
+
+static void sxl_report_leaks(void) {
+    size_t count = 0, total = 0;
+    for (sxl_allocation* a = sxl_allocations; a; a = a->next) {
+        if (a->freed_at) continue;
+        fprintf(stderr, "%s: leaked %zu bytes\n", a->loc, a->size);
+        count++;
+        total += a->size;
+    }
+    if (count) {
+        fprintf(stderr, "%zu allocation%s leaked, %zu bytes in total\n", count, count == 1 ? "" : "s", total);
+    }
+}
+
+static void* sxl_malloc(size_t size, const char* loc) {
+    if (!sxl_allocations) atexit(sxl_report_leaks);
+    void* ptr = malloc(size);
+    sxl_allocation* a = malloc(sizeof *a);
+    if (!ptr || !a) {
+        fflush(stdout);
+        fprintf(stderr, "%s: out of memory\n", loc);
+        abort();
+    }
+    *a = (sxl_allocation){ ptr, size, loc, NULL, sxl_allocations };
+    sxl_allocations = a;
+    return ptr;
+}
 
+static void sxl_free(void* ptr, const char* loc) {
+    for (sxl_allocation* a = sxl_allocations; a; a = a->next) {
+        if (a->ptr != ptr) continue;
+        if (a->freed_at) {
+            fflush(stdout);
+            fprintf(stderr, "%s: double free of memory allocated at %s, already freed at %s\n", loc, a->loc, a->freed_at);
+            abort();
+        }
+        a->freed_at = loc;
+        return;
+    }
+    fflush(stdout);
+    fprintf(stderr, "%s: free of memory not allocated by alloc\n", loc);
+    abort();
+}
+"#;
+
+/// Operations on `str`, which is a pointer to UTF-8 bytes and their count.
+/// Strings made by `+` are allocated like `alloc` does.
+const STRINGS: &str = r#"static inline void sxl_print(str s) {
+    fwrite(s.ptr, 1, s.len, stdout);
+}
+
+static inline int sxl_str_cmp(str a, str b) {
+    int cmp = memcmp(a.ptr, b.ptr, a.len < b.len ? a.len : b.len);
+    return cmp != 0 ? cmp : (a.len > b.len) - (a.len < b.len);
+}
+
+static inline str sxl_str_concat(str a, str b, const char* loc) {
+    char* ptr = sxl_malloc(a.len + b.len + 1, loc);
+    memcpy(ptr, a.ptr, a.len);
+    memcpy(ptr + a.len, b.ptr, b.len);
+    ptr[a.len + b.len] = '\0';
+    return (str){ ptr, a.len + b.len };
+}
+
+static inline bool sxl_is_char_boundary(str s, size_t index) {
+    return index >= s.len || (s.ptr[index] & 0xc0) != 0x80;
+}
+
+static inline str sxl_str_range(str s, size_t start, size_t end, const char* loc) {
+    if (end == SIZE_MAX) end = s.len;
+    if (loc) {
+        sxl_range_check(start, end, s.len, loc);
+        if (!sxl_is_char_boundary(s, start) || !sxl_is_char_boundary(s, end)) {
+            fflush(stdout);
+            fprintf(stderr, "%s: str slice %zu..%zu splits a character\n", loc, start, end);
+            abort();
+        }
+    }
+    return (str){ s.ptr + start, end - start };
+}
+"#;
+
+/// A loop being compiled.
+struct LoopLabel {
+    id: usize,
+    /// How many `switch` statements the code being compiled is nested in.
+    switches: usize,
+    /// Whether a `break` jumps to the label after the loop.
+    used: bool,
+}
+
+pub struct Compiler {
+    /// Numbers temporaries and labels so their names are unique.
+    next_id: Cell<usize>,
+    loops: RefCell<Vec<LoopLabel>>,
+    /// Names of the array and slice types, and allocation helpers, defined
+    /// so far.
+    defined_types: RefCell<HashSet<String>>,
+    /// Array and slice typedefs not yet written to the output.
+    pending_types: RefCell<String>,
+    /// Functions slicing and indexing each slice type and allocating each
+    /// type, which are written after every type is complete.
+    helpers: RefCell<String>,
+    /// Whether array indices are checked at runtime.
+    bounds_checks: bool,
+    /// Whether allocations are tracked to find leaks and double frees.
+    debug_alloc: bool,
 }
 
 impl Compiler {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            next_id: Cell::new(0),
+            loops: RefCell::new(vec![]),
+            defined_types: RefCell::new(HashSet::new()),
+            pending_types: RefCell::new(String::new()),
+            helpers: RefCell::new(String::new()),
+            bounds_checks: true,
+            debug_alloc: false,
+        }
+    }
+
+    /// Sets whether indexing with a non-constant index checks it against
+    /// the length of the array, aborting when it is out of bounds.
+    pub fn with_bounds_checks(mut self, enabled: bool) -> Self {
+        self.bounds_checks = enabled;
+        self
+    }
+
+    /// Sets whether `alloc` and `free` go through an allocator that reports
+    /// leaked memory at exit and aborts on freeing memory twice.
+    pub fn with_debug_alloc(mut self, enabled: bool) -> Self {
+        self.debug_alloc = enabled;
+        self
     }
 
     pub fn compile_program(&self, program: Program) -> String {
+        let type_decls = self.compile_type_decls(&program);
+        let prototypes = self.compile_prototypes(&program);
+        let body = program.body.iter()
+            .filter(|stmt| Self::type_decl(&stmt.kind).is_none())
+            .map(|stmt| self.compile_statement(stmt, 0))
+            .reduce(|acc, stmt| format!("{acc}\n{stmt}"))
+            .unwrap_or_default();
+
         format!(r#"// compiled from SXL
 #include <stdio.h>
+#include <stdlib.h>
+#include <stddef.h>
 #include <stdint.h>
+#include <stdbool.h>
+#include <string.h>
+typedef int8_t i8;
+typedef int16_t i16;
 typedef int32_t i32;
-typedef const char* str;
+typedef int64_t i64;
+typedef uint8_t u8;
+typedef uint16_t u16;
+typedef uint32_t u32;
+typedef uint64_t u64;
+typedef size_t usize;
+typedef float f32;
+typedef double f64;
+typedef struct {{ const char* ptr; usize len; }} str;
+
+static inline size_t sxl_bounds_check(size_t index, size_t len, const char* loc) {{
+    if (index >= len) {{
+        fflush(stdout);
+        fprintf(stderr, "%s: index out of bounds: the length is %zu but the index is %zu\n", loc, len, index);
+        abort();
+    }}
+    return index;
+}}
 
+static inline void sxl_range_check(size_t start, size_t end, size_t len, const char* loc) {{
+    if (start > end || end > len) {{
+        fflush(stdout);
+        fprintf(stderr, "%s: slice out of bounds: %zu..%zu of a length of %zu\n", loc, start, end, len);
+        abort();
+    }}
+}}
+
+{}
 {}
-            "#, program.body.iter()
-                .map(|stmt| self.compile_statement(stmt, 0))
-                .reduce(|acc, stmt| format!("{acc}\n{stmt}"))
-                .unwrap_or_default()
-        )
+{}{}{}
+{}
+{}
+            "#, if self.debug_alloc { DEBUG_ALLOCATOR } else { ALLOCATOR },
+            STRINGS,
+            type_decls, self.take_pending_types(), self.helpers.take(), prototypes, body)
     }
 
-    fn compile_statement(&self, stmt: &Statement, indent: i32) -> String {
-        use Statement::*;
+    /// Returns the C name of `vtype`. Arrays become structs wrapping a C
+    /// array, so that they are copied by value as in SXL, and slices become
+    /// a pointer and a length. Each is defined the first time it is used.
+    fn c_type(&self, vtype: &ValueType) -> String {
+        match vtype {
+            ValueType::Type(name) => name.clone(),
+            ValueType::Pointer(pointee) => format!("{}*", self.c_type(pointee)),
+            ValueType::Array(element, len) => {
+                let name = Self::mangle(vtype);
+                let element = self.c_type(element);
+                if self.defined_types.borrow_mut().insert(name.clone()) {
+                    self.pending_types.borrow_mut()
+                        .push_str(&format!("typedef struct {{ {element} data[{len}]; }} {name};\n"));
+                }
+                name
+            }
+            ValueType::Slice(element) => {
+                let name = Self::mangle(vtype);
+                let element = self.c_type(element);
+                if self.defined_types.borrow_mut().insert(name.clone()) {
+                    self.pending_types.borrow_mut()
+                        .push_str(&format!("typedef struct {{ {element} const* ptr; usize len; }} {name};\n"));
+                    self.helpers.borrow_mut().push_str(&format!(r#"static inline {name} {name}_range({name} s, size_t start, size_t end, const char* loc) {{
+    if (end == SIZE_MAX) end = s.len;
+    if (loc) sxl_range_check(start, end, s.len, loc);
+    return ({name}){{ s.ptr + start, end - start }};
+}}
+static inline {element} {name}_get({name} s, size_t index, const char* loc) {{
+    return s.ptr[sxl_bounds_check(index, s.len, loc)];
+}}
+"#));
+                }
+                name
+            }
+            ValueType::Func(_) => todo!(),
+            ValueType::Unknown => unreachable!("{vtype} was not type checked"),
+        }
+    }
+
+    /// Spells `vtype` as a C identifier, to name the struct an array or slice
+    /// lowers to.
+    fn mangle(vtype: &ValueType) -> String {
+        match vtype {
+            ValueType::Array(element, len) => format!("arr_{len}_{}", Self::mangle(element)),
+            ValueType::Slice(element) => format!("slice_{}", Self::mangle(element)),
+            ValueType::Pointer(pointee) => format!("ptr_{}", Self::mangle(pointee)),
+            vtype => vtype.to_string(),
+        }
+    }
+
+    /// Declares `name` as a `vtype`, which is `const` unless `mutable`.
+    fn compile_declaration(&self, vtype: &ValueType, name: &str, mutable: bool) -> String {
+        match (vtype, mutable) {
+            (_, true) => format!("{} {name}", self.c_type(vtype)),
+            // `const T* p` would make the pointee const instead.
+            (ValueType::Pointer(_), false) => format!("{} const {name}", self.c_type(vtype)),
+            (_, false) => format!("const {} {name}", self.c_type(vtype)),
+        }
+    }
+
+    /// Returns the array and slice typedefs used since the last call.
+    fn take_pending_types(&self) -> String {
+        self.pending_types.take()
+    }
 
-        let indent_str = "    ".repeat(indent as usize);
+    /// Spells `value` as a C string literal. Bytes other than printable ASCII
+    /// are written in octal, which unlike `\x` stops after three digits.
+    fn c_string(value: &str) -> String {
+        let mut out = "\"".to_owned();
+        let mut prev = 0;
+
+        for byte in value.bytes() {
+            match byte {
+                b'"' => out.push_str("\\\""),
+                b'\\' => out.push_str("\\\\"),
+                b'\n' => out.push_str("\\n"),
+                b'\t' => out.push_str("\\t"),
+                b'\r' => out.push_str("\\r"),
+                // `??` starts a trigraph.
+                b'?' if prev == b'?' => out.push_str("\\?"),
+                b' '..=b'~' => out.push(byte as char),
+                _ => out.push_str(&format!("\\{byte:03o}")),
+            }
+            prev = byte;
+        }
+
+        out.push('"');
+        out
+    }
+
+    /// Where `expr` is in the source, as a C string for runtime errors.
+    fn location(expr: &Expression) -> String {
+        format!("{:?}", expr.span.to_string())
+    }
+
+    /// Where a runtime check happens, or `NULL` if bounds checks are disabled.
+    fn check_location(&self, expr: &Expression) -> String {
+        if self.bounds_checks {
+            Self::location(expr)
+        } else {
+            "NULL".to_owned()
+        }
+    }
+
+    /// Returns the function allocating a copy of a `vtype`, defining it the
+    /// first time it is used.
+    fn alloc_helper(&self, vtype: &ValueType) -> String {
+        let name = format!("alloc_{}", Self::mangle(vtype));
+        let vtype = self.c_type(vtype);
+        if self.defined_types.borrow_mut().insert(name.clone()) {
+            self.helpers.borrow_mut().push_str(&format!(r#"static inline {vtype}* {name}({vtype} value, const char* loc) {{
+    {vtype}* ptr = sxl_malloc(sizeof(value), loc);
+    *ptr = value;
+    return ptr;
+}}
+"#));
+        }
+        name
+    }
+
+    /// Declares every top-level function up front so definitions can appear
+    /// in any order in the generated C.
+    fn compile_prototypes(&self, program: &Program) -> String {
+        program.body.iter()
+            .filter_map(|stmt| match &stmt.kind {
+                StmtKind::Func { decl, .. } => Some(format!("{};\n", self.compile_func_decl(decl))),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn compile_statement(&self, stmt: &Statement, indent: i32) -> String {
         format!("{}{}",
-            indent_str,
-            match stmt {
-                Let { name, vtype, value } => {
-                    match value {
-                        Some(value) => format!("{} {} = {};",
-                            vtype, name, self.compile_expression(value)),
-                        None => format!("{} {};",
-                            vtype, name),
-                    }
+            "    ".repeat(indent as usize),
+            self.compile_statement_kind(&stmt.kind, indent))
+    }
+
+    /// Compiles a statement without its leading indentation.
+    fn compile_statement_kind(&self, kind: &StmtKind, indent: i32) -> String {
+        use StmtKind::*;
+
+        match kind {
+            Let { name, mutable, vtype, value } => {
+                match value {
+                    Some(value) => format!("{} = {};",
+                        self.compile_declaration(&vtype.node, &name.node, *mutable),
+                        self.compile_expression(value)),
+                    None => format!("{} {};",
+                        self.c_type(&vtype.node), name.node),
+                }
+            }
+            Return { value } => format!("return {};",
+                self.compile_expression(value)),
+            If { cond, then, else_then } => format!("if ({}) {}{}",
+                self.compile_expression(cond),
+                self.compile_block_statement(then, indent),
+                match else_then {
+                    Some(else_then) => format!(" else {}",
+                        self.compile_statement_kind(&else_then.kind, indent)),
+                    None => "".to_string(),
+                }),
+            While { cond, body } => self.compile_loop(
+                format!("while ({})", self.compile_expression(cond)),
+                body, indent),
+            // The end of the range is evaluated once, before the first iteration.
+            For { var, start, end, body } => self.compile_loop(
+                format!("for ({} {var} = {}, {bound} = {}; {var} < {bound}; {var}++)",
+                    start.vtype,
+                    self.compile_expression(start),
+                    self.compile_expression(end),
+                    var = var.node,
+                    bound = format!("sxl_end{}", self.fresh_id())),
+                body, indent),
+            Break => match self.loops.borrow_mut().last_mut() {
+                Some(label) if label.switches > 0 => {
+                    label.used = true;
+                    format!("goto break_{};", label.id)
                 }
-                Return { value } => format!("return {};",
-                    self.compile_expression(value)),
-                If { cond, then, else_then } => format!("if ({}) {} {}",
-                    self.compile_expression(cond),
-                    self.compile_block_statement(then, indent),
-                    match else_then {
-                        Some(else_then) => format!("else {{\n{}\n{indent_str}}}",
-                            self.compile_statements(else_then, indent + 1)),
-                        None => "".to_string(),
-                    }),
-                Expression { value } => format!("{};",
-                    self.compile_expression(value)),
-                Block { body } => self.compile_block_statement(body, indent),
-                Func { decl, body } => format!("{} {}",
-                    self.compile_func_decl(decl),
-                    self.compile_block_statement(body, indent)),
+                _ => "break;".to_owned(),
+            },
+            Continue => "continue;".to_owned(),
+            Expression { value } => format!("{};",
+                self.compile_expression(value)),
+            Block { body } => self.compile_block_statement(body, indent),
+            Func { decl, body } => format!("{} {}",
+                self.compile_func_decl(decl),
+                self.compile_block_statement(body, indent)),
+            Struct { decl } => self.compile_struct_decl(decl, indent),
+            Enum { decl } => self.compile_enum_decl(decl, indent),
+            Match { value, arms } => self.compile_match(value, arms, indent),
+        }
+    }
+
+    fn compile_struct_decl(&self, decl: &StructDecl, indent: i32) -> String {
+        format!("typedef struct {name} {{\n{}{}}} {name};",
+            decl.fields.iter()
+                .map(|field| format!("{}{} {};\n",
+                    "    ".repeat(indent as usize + 1), self.c_type(&field.vtype), field.name))
+                .collect::<String>(),
+            "    ".repeat(indent as usize),
+            name = decl.name)
+    }
+
+    /// Returns the name of a struct or enum declaration and the types it
+    /// holds by value.
+    fn type_decl(kind: &StmtKind) -> Option<(&str, Vec<&ValueType>)> {
+        match kind {
+            StmtKind::Struct { decl } => Some((&decl.name,
+                decl.fields.iter().map(|field| &field.vtype).collect())),
+            StmtKind::Enum { decl } => Some((&decl.name,
+                decl.variants.iter().flat_map(|variant| &variant.fields).collect())),
+            _ => None,
+        }
+    }
+
+    /// Defines every top-level struct and enum before anything uses it. A type
+    /// is emitted after the types it holds by value, as C requires.
+    fn compile_type_decls(&self, program: &Program) -> String {
+        fn element_name(vtype: &ValueType) -> String {
+            match vtype {
+                ValueType::Array(element, _) | ValueType::Slice(element) | ValueType::Pointer(element) =>
+                    element_name(element),
+                vtype => vtype.to_string(),
             }
-        )
+        }
+
+        fn visit<'a>(kind: &'a StmtKind, decls: &[&'a StmtKind], visited: &mut Vec<&'a str>, order: &mut Vec<&'a StmtKind>) {
+            let Some((name, members)) = Compiler::type_decl(kind) else { return };
+            if visited.contains(&name) {
+                return;
+            }
+            visited.push(name);
+
+            // Types behind a slice or pointer only have to be declared, but
+            // ordering them first too keeps arrays of them complete.
+            for member in members {
+                let inner = decls.iter()
+                    .find(|inner| Compiler::type_decl(inner)
+                        .is_some_and(|(inner, _)| element_name(member) == inner));
+                if let Some(inner) = inner {
+                    visit(inner, decls, visited, order);
+                }
+            }
+            order.push(kind);
+        }
+
+        let decls: Vec<_> = program.body.iter()
+            .map(|stmt| &stmt.kind)
+            .filter(|kind| Self::type_decl(kind).is_some())
+            .collect();
+
+        let mut order = vec![];
+        let mut visited = vec![];
+        for decl in &decls {
+            visit(decl, &decls, &mut visited, &mut order);
+        }
+
+        // Declaring every type up front lets slices point to types defined
+        // later, including the type holding the slice.
+        let forward: String = order.iter()
+            .filter_map(|kind| Self::type_decl(kind))
+            .map(|(name, _)| format!("typedef struct {name} {name};\n"))
+            .collect();
+
+        // Array typedefs go right before the first type using them, which
+        // comes after the type of their elements.
+        forward + &order.iter()
+            .map(|kind| {
+                let decl = self.compile_statement_kind(kind, 0);
+                format!("{}{decl}\n", self.take_pending_types())
+            })
+            .collect::<String>()
+    }
+
+    /// Lowers an enum to a struct holding a tag and a union of the payloads.
+    fn compile_enum_decl(&self, decl: &EnumDecl, indent: i32) -> String {
+        let pad = "    ".repeat(indent as usize + 1);
+        let payloads: String = decl.variants.iter()
+            .filter(|variant| !variant.fields.is_empty())
+            .map(|variant| format!("{pad}    struct {{ {}}} {};\n",
+                variant.fields.iter().enumerate()
+                    .map(|(i, vtype)| format!("{} _{i}; ", self.c_type(vtype)))
+                    .collect::<String>(),
+                variant.name))
+            .collect();
+
+        format!("typedef struct {name} {{\n{pad}enum {{ {} }} tag;\n{}{}}} {name};",
+            decl.variants.iter()
+                .map(|variant| Self::variant_tag(&decl.name, &variant.name))
+                .collect::<Vec<_>>()
+                .join(", "),
+            if payloads.is_empty() { payloads } else { format!("{pad}union {{\n{payloads}{pad}}};\n") },
+            "    ".repeat(indent as usize),
+            name = decl.name)
+    }
+
+    fn variant_tag(enum_name: &str, variant: &str) -> String {
+        format!("{enum_name}_{variant}")
+    }
+
+    /// Lowers a `match` to a `switch` on the tag of a copy of the value.
+    fn compile_match(&self, value: &Expression, arms: &[MatchArm], indent: i32) -> String {
+        let pad = "    ".repeat(indent as usize + 1);
+        let temp = format!("sxl_m{}", self.fresh_id());
+        let enum_name = value.vtype.to_string();
+
+        if let Some(label) = self.loops.borrow_mut().last_mut() {
+            label.switches += 1;
+        }
+
+        // Unreachable arms are dropped, as C rejects repeated labels.
+        let mut reachable: Vec<&MatchArm> = vec![];
+        for arm in arms {
+            let seen = reachable.iter().any(|prev| match (&prev.pattern.node, &arm.pattern.node) {
+                (Pattern::Wildcard, _) => true,
+                (Pattern::Variant { variant: prev, .. }, Pattern::Variant { variant, .. }) => prev.node == variant.node,
+                _ => false,
+            });
+            if !seen {
+                reachable.push(arm);
+            }
+        }
+
+        let cases: String = reachable.iter().enumerate()
+            .map(|(i, arm)| {
+                let (case, bindings) = match &arm.pattern.node {
+                    Pattern::Wildcard => ("default:".to_owned(), String::new()),
+                    Pattern::Variant { variant, bindings, .. } => (
+                        // Matches are exhaustive, so the last arm can take
+                        // every remaining value. This tells the C compiler
+                        // that the `switch` covers every case.
+                        if i + 1 == reachable.len() {
+                            "default:".to_owned()
+                        } else {
+                            format!("case {}:", Self::variant_tag(&enum_name, &variant.node))
+                        },
+                        bindings.iter().enumerate()
+                            .filter(|(_, binding)| binding.name != "_")
+                            .map(|(i, binding)| format!("{pad}    {} = {temp}.{}._{i};\n",
+                                self.compile_symbol(binding), variant.node))
+                            .collect(),
+                    ),
+                };
+
+                format!("{pad}{case} {{\n{bindings}{}{pad}    break;\n{pad}}}\n",
+                    arm.body.iter()
+                        .map(|stmt| format!("{}\n", self.compile_statement(stmt, indent + 2)))
+                        .collect::<String>())
+            })
+            .collect();
+
+        if let Some(label) = self.loops.borrow_mut().last_mut() {
+            label.switches -= 1;
+        }
+
+        format!("{{\n{pad}const {enum_name} {temp} = {};\n{pad}switch ({temp}.tag) {{\n{cases}{pad}}}\n{}}}",
+            self.compile_expression(value),
+            "    ".repeat(indent as usize))
+    }
+
+    fn fresh_id(&self) -> usize {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        id
+    }
+
+    /// Compiles a loop whose body is `body`. A `break` inside a `switch` in
+    /// the body would only leave the `switch`, so those jump to a label
+    /// placed after the loop instead.
+    fn compile_loop(&self, header: String, body: &BlockStmt, indent: i32) -> String {
+        let id = self.fresh_id();
+        self.loops.borrow_mut().push(LoopLabel { id, switches: 0, used: false });
+        let body = self.compile_block_statement(body, indent);
+        let label = self.loops.borrow_mut().pop().unwrap();
+
+        if label.used {
+            format!("{header} {body}\n{}break_{id}:;", "    ".repeat(indent as usize))
+        } else {
+            format!("{header} {body}")
+        }
     }
 
     fn compile_block_statement(&self, block: &BlockStmt, indent: i32) -> String {
@@ -72,45 +620,600 @@ typedef const char* str;
             .unwrap_or_default()
     }
 
+    /// The C precedence of a binary operator; higher binds tighter.
+    fn binary_precedence(op: &Token) -> u8 {
+        match op {
+            Token::Asterisk | Token::Slash | Token::Percent => 13,
+            Token::Plus | Token::Minus => 12,
+            Token::Shl | Token::Shr => 11,
+            Token::Lt | Token::Gt | Token::Lte | Token::Gte => 10,
+            Token::Equal | Token::NotEqual => 9,
+            Token::Ampersand => 8,
+            Token::Caret => 7,
+            Token::Pipe => 6,
+            Token::And => 5,
+            Token::Or => 4,
+            op => unreachable!("{op} is not a binary operator"),
+        }
+    }
+
+    fn precedence(expr: &Expression) -> u8 {
+        match &expr.kind {
+            // Concatenation compiles to a call.
+            ExprKind::Binary { op: Token::Plus, left, .. } if left.vtype == ValueType::str() => POSTFIX,
+            _ if Self::needs_truncation(expr) => UNARY,
+            ExprKind::Unary { .. } | ExprKind::Cast { .. } => UNARY,
+            ExprKind::Binary { op, .. } => Self::binary_precedence(op),
+            ExprKind::Assign { .. } => ASSIGN,
+            _ => POSTFIX,
+        }
+    }
+
+    /// Whether `expr` must be cast back to its type. C promotes operands
+    /// narrower than `int`, so `u8` arithmetic could otherwise pass 255.
+    fn needs_truncation(expr: &Expression) -> bool {
+        let narrow = matches!(expr.vtype.to_string().as_str(), "i8" | "i16" | "u8" | "u16");
+
+        narrow && match &expr.kind {
+            ExprKind::Unary { op, .. } => matches!(op, Token::Minus | Token::Tilde),
+            ExprKind::Binary { op, .. } => matches!(op,
+                Token::Plus | Token::Minus | Token::Asterisk | Token::Slash | Token::Percent | Token::Shl),
+            _ => false,
+        }
+    }
+
+    /// Casts the compiled `code` of `expr` back to its type if it needs it.
+    fn truncate(&self, expr: &Expression, code: String) -> String {
+        if Self::needs_truncation(expr) {
+            format!("({})({code})", self.c_type(&expr.vtype))
+        } else {
+            code
+        }
+    }
+
+    /// Compiles `expr`, wrapped in parentheses if it binds looser than `min`.
+    fn compile_operand(&self, expr: &Expression, min: u8) -> String {
+        let out = self.compile_expression(expr);
+
+        if Self::precedence(expr) < min {
+            format!("({out})")
+        } else {
+            out
+        }
+    }
+
     fn compile_expression(&self, expr: &Expression) -> String {
         use ExprKind::*;
 
         match &expr.kind {
             Ident { value } => value.to_string(),
-            Int { value } => value.to_string(),
-            String { value } => format!("\"{value}\""),
-            Unary { op, right } => format!("{op}{}",
-                self.compile_expression(right)),
-            Binary { op, left, right } => format!("{} {op} {}",
-                self.compile_expression(left),
-                self.compile_expression(right)),
-            Call { func, args } => format!("{}({})",
-                self.compile_expression(func),
+            Int { value, .. } => match expr.vtype.to_string().as_str() {
+                "i64" => format!("{value}LL"),
+                "u64" | "usize" => format!("{value}ULL"),
+                "u32" => format!("{value}U"),
+                _ => value.to_string(),
+            },
+            Float { value, .. } if expr.vtype.to_string() == "f32" => format!("{value:?}f"),
+            Float { value, .. } => format!("{value:?}"),
+            String { value } => format!("(str){{ {}, {} }}", Self::c_string(value), value.len()),
+            Bool { value } => value.to_string(),
+            // The negated literal would not fit in `long long`.
+            Unary { op: Token::Minus, right } if matches!(right.kind, Int { value: 0x8000_0000_0000_0000, .. }) =>
+                "INT64_MIN".to_owned(),
+            Unary { op, right } => {
+                let right = self.compile_operand(right, UNARY);
+                // Keep `- -a` from turning into the C decrement operator.
+                let code = if right.starts_with('-') {
+                    format!("{op}({right})")
+                } else {
+                    format!("{op}{right}")
+                };
+                self.truncate(expr, code)
+            }
+            Binary { op: Token::Plus, left, right } if left.vtype == ValueType::str() =>
+                format!("sxl_str_concat({}, {}, {})",
+                    self.compile_expression(left),
+                    self.compile_expression(right),
+                    Self::location(expr)),
+            Binary { op, left, right } if left.vtype == ValueType::str() =>
+                format!("sxl_str_cmp({}, {}) {op} 0",
+                    self.compile_expression(left),
+                    self.compile_expression(right)),
+            Binary { op, left, right } => {
+                let prec = Self::binary_precedence(op);
+                let code = format!("{} {op} {}",
+                    self.compile_operand(left, prec),
+                    self.compile_operand(right, prec + 1));
+                self.truncate(expr, code)
+            }
+            Assign { op, target, value } => format!("{} {}= {}",
+                self.compile_operand(target, ASSIGN + 1),
+                op.as_ref().map(Token::to_string).unwrap_or_default(),
+                self.compile_operand(value, ASSIGN)),
+            StructLit { fields, .. } => format!("({}){{ {} }}",
+                expr.vtype,
+                fields.iter()
+                    .map(|(field, value)| format!(".{} = {}", field.node, self.compile_expression(value)))
+                    .reduce(|acc, s| format!("{acc}, {s}"))
+                    .unwrap_or_default()),
+            Variant { variant, args, .. } if args.is_empty() => format!("({}){{ .tag = {} }}",
+                expr.vtype,
+                Self::variant_tag(&expr.vtype.to_string(), &variant.node)),
+            Variant { variant, args, .. } => format!("({}){{ .tag = {}, .{} = {{ {} }} }}",
+                expr.vtype,
+                Self::variant_tag(&expr.vtype.to_string(), &variant.node),
+                variant.node,
                 args.iter()
                     .map(|arg| self.compile_expression(arg))
                     .reduce(|acc, s| format!("{acc}, {s}"))
                     .unwrap_or_default()),
+            Field { value, field } => match &value.vtype {
+                // `.len` is the only field of an array, and a constant.
+                ValueType::Array(_, len) => format!("{len}ULL"),
+                _ => format!("{}.{}",
+                    self.compile_operand(value, POSTFIX),
+                    field.node),
+            },
+            Array { elements } => format!("({}){{ {{ {} }} }}",
+                self.c_type(&expr.vtype),
+                elements.iter()
+                    .map(|element| self.compile_expression(element))
+                    .reduce(|acc, s| format!("{acc}, {s}"))
+                    .unwrap_or_default()),
+            Index { value, index } => self.compile_index(value, index),
+            Slice { value, start, end } if value.vtype == ValueType::str() =>
+                format!("sxl_str_range({}, {}, {}, {})",
+                    self.compile_expression(value),
+                    start.as_ref().map_or("0".to_owned(), |start| self.compile_expression(start)),
+                    end.as_ref().map_or("SIZE_MAX".to_owned(), |end| self.compile_expression(end)),
+                    self.check_location(expr)),
+            Slice { value, start, end } => {
+                let slice = self.c_type(&expr.vtype);
+                let whole = match &value.vtype {
+                    ValueType::Array(_, len) => format!("({slice}){{ {}.data, {len} }}",
+                        self.compile_operand(value, POSTFIX)),
+                    _ => self.compile_expression(value),
+                };
+
+                format!("{slice}_range({whole}, {}, {}, {})",
+                    start.as_ref().map_or("0".to_owned(), |start| self.compile_expression(start)),
+                    end.as_ref().map_or("SIZE_MAX".to_owned(), |end| self.compile_expression(end)),
+                    self.check_location(expr))
+            }
+            Cast { value, .. } => format!("({}){}",
+                expr.vtype,
+                self.compile_operand(value, UNARY)),
+            Call { func, args } => match (&func.vtype, &expr.vtype) {
+                (ValueType::Func(decl), ValueType::Pointer(pointee)) if decl.is_builtin("alloc") =>
+                    format!("{}({}, {})",
+                        self.alloc_helper(pointee),
+                        self.compile_expression(&args[0]),
+                        Self::location(expr)),
+                (ValueType::Func(decl), _) if decl.is_builtin("free") => {
+                    let ptr = match &args[0].vtype {
+                        ValueType::Pointer(_) => self.compile_expression(&args[0]),
+                        _ => format!("(void*){}.ptr", self.compile_operand(&args[0], POSTFIX)),
+                    };
+
+                    if self.debug_alloc {
+                        format!("sxl_free({ptr}, {})", Self::location(expr))
+                    } else {
+                        format!("free({ptr})")
+                    }
+                }
+                (ValueType::Func(decl), _) if decl.is_builtin("printf") =>
+                    format!("sxl_print({})", self.compile_expression(&args[0])),
+                _ => self.compile_call(func, args),
+            },
         }
     }
 
-    fn compile_func_decl(&self, decl: &FuncDecl) -> String {
-        match decl.vtype.as_ref() {
-            ValueType::Type(vtype) =>
-                format!("{} {}({})", 
-                    vtype, decl.name,
-                    decl.params.iter()
-                        .map(|param| self.compile_symbol(param))
-                        .reduce(|acc, s| format!("{acc}, {s}"))
-                        .unwrap_or_default()),
-            _ => todo!(),
+    fn compile_call(&self, func: &Expression, args: &[Expression]) -> String {
+        format!("{}({})",
+            self.compile_operand(func, POSTFIX),
+            args.iter()
+                .map(|arg| self.compile_expression(arg))
+                .reduce(|acc, s| format!("{acc}, {s}"))
+                .unwrap_or_default())
+    }
+
+    /// Compiles `value[index]`. Indices into arrays are bounds checked unless
+    /// they are constants, which the type checker has checked; indices into
+    /// slices always are.
+    fn compile_index(&self, value: &Expression, index: &Expression) -> String {
+        let out = self.compile_expression(index);
+
+        match &value.vtype {
+            ValueType::Slice(_) if self.bounds_checks => format!("{}_get({}, {out}, {})",
+                self.c_type(&value.vtype),
+                self.compile_expression(value),
+                self.check_location(index)),
+            ValueType::Slice(_) => format!("{}.ptr[{out}]",
+                self.compile_operand(value, POSTFIX)),
+            ValueType::Array(_, len) if self.bounds_checks && !matches!(index.kind, ExprKind::Int { .. }) =>
+                format!("{}.data[sxl_bounds_check({out}, {len}, {})]",
+                    self.compile_operand(value, POSTFIX),
+                    self.check_location(index)),
+            _ => format!("{}.data[{out}]",
+                self.compile_operand(value, POSTFIX)),
         }
     }
 
+    fn compile_func_decl(&self, decl: &FuncDecl) -> String {
+        format!("{} {}({})",
+            self.c_type(&decl.vtype), decl.name,
+            decl.params.iter()
+                .map(|param| self.compile_symbol(param))
+                .reduce(|acc, s| format!("{acc}, {s}"))
+                .unwrap_or_else(|| "void".to_owned()))
+    }
+
     fn compile_symbol(&self, symbol: &Symbol) -> String {
-        match &symbol.vtype {
-            ValueType::Type(vtype) => format!("{} {}",
-                vtype, symbol.name),
-            ValueType::Func(_) => todo!(),
+        self.compile_declaration(&symbol.vtype, &symbol.name, symbol.mutable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, typeck::TypeChecker};
+
+    fn compile(input: &str) -> String {
+        let lexer = Lexer::new(input.as_bytes().to_vec());
+        let mut program = Parser::new(lexer).unwrap().parse_program().unwrap();
+        TypeChecker::new().check_program(&mut program).unwrap();
+        Compiler::new().compile_program(program)
+    }
+
+    #[test]
+    fn test_prototypes() {
+        let out = compile("fn main() -> i32 { return helper(1); }
+fn helper(n: i32) -> i32 { return n; }");
+
+        let protos = out.find("i32 main(void);").unwrap();
+        let helper = out.find("i32 helper(const i32 n);").unwrap();
+        let main = out.find("i32 main(void) {").unwrap();
+        assert!(protos < main && helper < main);
+    }
+
+    #[test]
+    fn test_casts() {
+        let out = compile("fn f(a: i64) -> f32 { return -a as f32 * 2.0; }");
+
+        assert!(out.contains("return (f32)-a * 2.0f;"));
+    }
+
+    #[test]
+    fn test_precedence() {
+        let out = compile("fn f(mut a: i32, mut b: i32, c: i32) -> i32 {
+    let x: i32 = (a + b) * c;
+    let y: i32 = a - (b - c) / (a * b);
+    let z: i32 = (a - b) - c + -(-a);
+    let p: bool = !(a < b && (b < c || c < a)) == (x > 0);
+    a = b = (c + 1) * 2;
+    return -(a + b) as i32 * (c as i32);
+}");
+
+        assert!(out.contains("i32 x = (a + b) * c;"));
+        assert!(out.contains("i32 y = a - (b - c) / (a * b);"));
+        assert!(out.contains("i32 z = a - b - c + -(-a);"));
+        assert!(out.contains("bool p = !(a < b && (b < c || c < a)) == x > 0;"));
+        assert!(out.contains("a = b = (c + 1) * 2;"));
+        assert!(out.contains("return (i32)-(a + b) * (i32)c;"));
+    }
+
+    #[test]
+    fn test_narrow_integers() {
+        let out = compile("fn f(x: u8, y: i16) -> bool {
+    let a: u8 = x + 10;
+    let b = -y * 2i16;
+    let c: i64 = -9223372036854775808;
+    return x + 10 < 100 && (x * 2) as i32 > 0;
+}");
+
+        assert!(out.contains("u8 a = (u8)(x + 10);"));
+        assert!(out.contains("i16 b = (i16)((i16)(-y) * 2);"));
+        assert!(out.contains("i64 c = INT64_MIN;"));
+        assert!(out.contains("return (u8)(x + 10) < 100 && (i32)(u8)(x * 2) > 0;"));
+    }
+
+    #[test]
+    fn test_bitwise() {
+        let out = compile("fn f(a: u32, b: u32) -> u32 {
+    return (a | b) & ~(a ^ b) << 2 % 3;
+}");
+
+        assert!(out.contains("return (a | b) & ~(a ^ b) << 2 % 3;"));
+
+        let out = compile("fn f(b: u8, s: u16) -> bool {
+    return ~b == 255 && s << 20 == 0 && (b & ~b) >> 1 == 0;
+}");
+
+        assert!(out.contains("return (u8)(~b) == 255 && (u16)(s << 20) == 0 && (b & (u8)(~b)) >> 1 == 0;"));
+    }
+
+    #[test]
+    fn test_assignment() {
+        let out = compile("fn f(mut a: i32, mut b: i32) -> i32 {
+    a += b *= 2;
+    a = (b = 1) + 1;
+    a >>= 1;
+    return a;
+}");
+
+        assert!(out.contains("a += b *= 2;"));
+        assert!(out.contains("a = (b = 1) + 1;"));
+        assert!(out.contains("a >>= 1;"));
+    }
+
+    #[test]
+    fn test_const_bindings() {
+        let out = compile("fn f(a: i32, mut b: i32) -> i32 {
+    let c: i32 = a;
+    let mut d: i32 = b;
+    return c + d;
+}");
+
+        assert!(out.contains("i32 f(const i32 a, i32 b) {"));
+        assert!(out.contains("const i32 c = a;"));
+        assert!(out.contains("    i32 d = b;"));
+    }
+
+    #[test]
+    fn test_inferred_let() {
+        let out = compile("fn f(a: i64) -> i64 {
+    let mut b = a * 2;
+    let c = 1.5f32;
+    return b;
+}");
+
+        assert!(out.contains("    i64 b = a * 2LL;"));
+        assert!(out.contains("const f32 c = 1.5f;"));
+    }
+
+    #[test]
+    fn test_arrays() {
+        let program = "struct Grid { cells: [[i32; 2]; 2] }
+fn f(mut xs: [u8; 3], i: usize) -> [u8; 3] {
+    let g = Grid { cells: [[1, 2], [3, 4]] };
+    xs[i] = xs[0] + g.cells[1][i] as u8;
+    return xs;
+}";
+        let out = compile(program);
+
+        let inner = out.find("typedef struct { i32 data[2]; } arr_2_i32;").unwrap();
+        let outer = out.find("typedef struct { arr_2_i32 data[2]; } arr_2_arr_2_i32;").unwrap();
+        let grid = out.find("typedef struct Grid {\n    arr_2_arr_2_i32 cells;\n} Grid;").unwrap();
+        let bytes = out.find("typedef struct { u8 data[3]; } arr_3_u8;").unwrap();
+        let proto = out.find("arr_3_u8 f(arr_3_u8 xs, const usize i);").unwrap();
+        assert!(inner < outer && outer < grid && grid < bytes && bytes < proto);
+        assert!(out.contains("const Grid g = (Grid){ .cells = (arr_2_arr_2_i32){ { (arr_2_i32){ { 1, 2 } }, (arr_2_i32){ { 3, 4 } } } } };"));
+        assert!(out.contains(r#"xs.data[sxl_bounds_check(i, 3, "<input>:4:8")] = (u8)(xs.data[0ULL] + (u8)g.cells.data[1ULL].data[sxl_bounds_check(i, 2, "<input>:4:32")]);"#));
+        // What the program printed must not be lost when a check aborts it.
+        assert!(out.contains("    if (index >= len) {\n        fflush(stdout);\n        fprintf(stderr, "));
+
+        let lexer = Lexer::new(program.as_bytes().to_vec());
+        let mut program = Parser::new(lexer).unwrap().parse_program().unwrap();
+        TypeChecker::new().check_program(&mut program).unwrap();
+        let out = Compiler::new().with_bounds_checks(false).compile_program(program);
+        assert!(out.contains("xs.data[i] = (u8)(xs.data[0ULL] + (u8)g.cells.data[1ULL].data[i]);"));
+    }
+
+    #[test]
+    fn test_slices() {
+        let out = compile("struct Tree { value: i32, children: []Tree }
+fn sum(xs: []i32, lo: usize) -> i32 {
+    return xs[lo..][0] + xs[..2].len as i32;
+}
+fn f(a: [i32; 3]) -> i32 {
+    return sum(a[1..], a.len);
+}");
+
+        let forward = out.find("typedef struct Tree Tree;").unwrap();
+        let slice = out.find("typedef struct { Tree const* ptr; usize len; } slice_Tree;").unwrap();
+        let tree = out.find("typedef struct Tree {").unwrap();
+        let helper = out.find("static inline i32 slice_i32_get(slice_i32 s, size_t index, const char* loc) {").unwrap();
+        let proto = out.find("i32 sum(const slice_i32 xs, const usize lo);").unwrap();
+        assert!(forward < slice && slice < tree && tree < helper && helper < proto);
+        assert!(out.contains(r#"return slice_i32_get(slice_i32_range(xs, lo, SIZE_MAX, "<input>:3:12"), 0ULL, "<input>:3:21") + (i32)slice_i32_range(xs, 0, 2ULL, "<input>:3:26").len;"#));
+        assert!(out.contains(r#"return sum(slice_i32_range((slice_i32){ a.data, 3 }, 1ULL, SIZE_MAX, "<input>:6:16"), 3ULL);"#));
+    }
+
+    #[test]
+    fn test_pointers() {
+        let out = compile("struct Point { x: i32, y: i32 }
+fn swap(p: *Point, mut out: *i32) -> *i32 {
+    let t = (*p).x;
+    (*p).x = (*p).y;
+    *out = -*out + t;
+    return out;
+}
+fn f(mut pt: Point, mut xs: [*i32; 2]) -> i32 {
+    let q: **i32 = &xs[0];
+    let grid = [xs];
+    let s: [][*i32; 2] = grid[..];
+    return *swap(&pt, &pt.y) & **q;
+}");
+
+        assert!(out.contains("i32* swap(Point* const p, i32* out) {"));
+        assert!(out.contains("    (*p).x = (*p).y;\n    *out = -*out + t;"));
+        assert!(out.contains("typedef struct { i32* data[2]; } arr_2_ptr_i32;"));
+        assert!(out.contains("typedef struct { arr_2_ptr_i32 const* ptr; usize len; } slice_arr_2_ptr_i32;"));
+        assert!(out.contains("i32** const q = &xs.data[0ULL];"));
+        assert!(out.contains("return *swap(&pt, &pt.y) & **q;"));
+    }
+
+    #[test]
+    fn test_allocation() {
+        let program = "fn f() -> i32 {
+    let p: *u8 = alloc(1);
+    let q = alloc(alloc(2));
+    free(*q);
+    free(q);
+    free(p);
+    return 0;
+}";
+        let out = compile(program);
+
+        assert!(out.contains("static inline u8* alloc_u8(u8 value, const char* loc) {\n    u8* ptr = sxl_malloc(sizeof(value), loc);"));
+        assert!(out.contains("static inline i32** alloc_ptr_i32(i32* value, const char* loc) {"));
+        assert!(out.contains(r#"i32** const q = alloc_ptr_i32(alloc_i32(2, "<input>:3:19"), "<input>:3:13");"#));
+        assert!(out.contains("    free(*q);\n    free(q);"));
+        assert!(!out.contains("sxl_report_leaks"));
+
+        let lexer = Lexer::new(program.as_bytes().to_vec());
+        let mut program = Parser::new(lexer).unwrap().parse_program().unwrap();
+        TypeChecker::new().check_program(&mut program).unwrap();
+        let out = Compiler::new().with_debug_alloc(true).compile_program(program);
+        assert!(out.contains("atexit(sxl_report_leaks);"));
+        assert!(out.contains(r#"    sxl_free(*q, "<input>:4:5");"#));
+    }
+
+    #[test]
+    fn test_strings() {
+        let out = compile(r#"fn f(a: str) -> bool {
+    printf("say \"hi\"\t\u{e9}??!\n");
+    let s = a + "!";
+    let t = s + a;
+    free(s);
+    let part = t[1..];
+    let longer = t.len > 2 && a < part;
+    free(t);
+    return longer;
+}"#);
+
+        assert!(out.contains("typedef struct { const char* ptr; usize len; } str;"));
+        assert!(out.contains(r#"sxl_print((str){ "say \"hi\"\t\303\251?\?!\n", 15 });"#));
+        assert!(out.contains(r#"const str s = sxl_str_concat(a, (str){ "!", 1 }, "<input>:3:13");"#));
+        assert!(out.contains(r#"const str t = sxl_str_concat(s, a, "<input>:4:13");"#));
+        assert!(out.contains("free((void*)s.ptr);"));
+        assert!(out.contains(r#"const str part = sxl_str_range(t, 1ULL, SIZE_MAX, "<input>:6:16");"#));
+        assert!(out.contains("const bool longer = t.len > 2ULL && sxl_str_cmp(a, part) < 0;"));
+        assert!(!out.contains("sxl_str_append"));
+    }
+
+    #[test]
+    fn test_structs() {
+        let out = compile("fn origin() -> Line {
+    return Line { from: Point { x: 0, y: 0 }, to: Point { y: 1, x: 2 } };
+}
+struct Line { from: Point, to: Point }
+struct Point { x: i32, y: i32 }");
+
+        let point = out.find("typedef struct Point {\n    i32 x;\n    i32 y;\n} Point;").unwrap();
+        let line = out.find("typedef struct Line {").unwrap();
+        let proto = out.find("Line origin(void);").unwrap();
+        assert!(point < line && line < proto);
+        assert!(out.contains("return (Line){ .from = (Point){ .x = 0, .y = 0 }, .to = (Point){ .y = 1, .x = 2 } };"));
+    }
+
+    #[test]
+    fn test_enums() {
+        let out = compile("enum Shape { Circle(i32), Rect(i32, i32), Empty }
+fn f(s: Shape) -> i32 {
+    let mut n: i32 = 0;
+    while true {
+        match s {
+            Shape::Circle(r) => { break; }
+            Shape::Rect(_, h) => { n += h; }
+            Shape::Circle(r) => { }
+            Shape::Empty => { continue; }
+        }
+        break;
+    }
+    return n;
+}
+fn g() -> Shape { return Shape::Rect(1, 2); }");
+
+        assert!(out.contains("typedef struct Shape {
+    enum { Shape_Circle, Shape_Rect, Shape_Empty } tag;
+    union {
+        struct { i32 _0; } Circle;
+        struct { i32 _0; i32 _1; } Rect;
+    };
+} Shape;"));
+        assert!(out.contains("    while (true) {
+        {
+            const Shape sxl_m1 = s;
+            switch (sxl_m1.tag) {
+            case Shape_Circle: {
+                const i32 r = sxl_m1.Circle._0;
+                goto break_0;
+                break;
+            }
+            case Shape_Rect: {
+                const i32 h = sxl_m1.Rect._1;
+                n += h;
+                break;
+            }
+            default: {
+                continue;
+                break;
+            }
+            }
         }
+        break;
+    }
+    break_0:;"));
+        assert!(out.contains("return (Shape){ .tag = Shape_Rect, .Rect = { 1, 2 } };"));
+    }
+
+    #[test]
+    fn test_loops() {
+        let out = compile("fn main() -> i32 {
+    let mut n: i32 = 0;
+    for i in 0..10 {
+        while n > i { break; }
+        continue;
+    }
+    return n;
+}");
+
+        assert!(out.contains("    for (i32 i = 0, sxl_end0 = 10; i < sxl_end0; i++) {
+        while (n > i) {
+            break;
+        }
+        continue;
+    }"));
+
+        let out = compile("fn calls(n: *i32) -> i32 {
+    *n += 1;
+    return 3;
+}
+fn main() -> i32 {
+    let mut count = 0;
+    let mut n = 3;
+    for i in 0..calls(&count) {
+        n += 1;
+    }
+    return count;
+}");
+        assert!(out.contains("for (i32 i = 0, sxl_end0 = calls(&count); i < sxl_end0; i++) {"));
+    }
+
+    #[test]
+    fn test_else_if() {
+        let out = compile("fn main() -> i32 {
+    let a: i32 = 1;
+    if a > 2 {
+        return 2;
+    } else if a == 1 {
+        if a < 0 { return 3; }
+    } else {
+        return 0;
+    }
+    return 1;
+}");
+
+        assert!(out.contains("    if (a > 2) {
+        return 2;
+    } else if (a == 1) {
+        if (a < 0) {
+            return 3;
+        }
+    } else {
+        return 0;
+    }
+    return 1;"));
     }
 }
@@ -0,0 +1,246 @@
+use crate::token::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// A compiler message pointing at one primary location in the source, with
+/// optional secondary locations and free-form notes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<&'static str>,
+    pub message: String,
+    /// Boxed to keep `Result<T, Diagnostic>` small.
+    pub primary: Box<Label>,
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, span: Span, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code: None,
+            message: message.into(),
+            primary: Box::new(Label { span, message: String::new() }),
+            secondary: vec![],
+            notes: vec![],
+        }
+    }
+
+    pub fn error(span: Span, message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, span, message)
+    }
+
+    pub fn warning(span: Span, message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, span, message)
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    /// Sets the text printed next to the primary underline.
+    pub fn with_primary(mut self, message: impl Into<String>) -> Self {
+        self.primary.message = message.into();
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.secondary.push(Label { span, message: message.into() });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.primary.span, self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+
+/// Renders diagnostics against the source they were produced from, in the
+/// style of rustc: a header, the offending lines and underlines beneath them.
+pub struct Emitter<'a> {
+    source: &'a [u8],
+    color: bool,
+}
+
+impl<'a> Emitter<'a> {
+    pub fn new(source: &'a [u8], color: bool) -> Self {
+        Self { source, color }
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color && !text.is_empty() {
+            format!("{style}{text}{RESET}")
+        } else {
+            text.to_owned()
+        }
+    }
+
+    fn severity_style(severity: Severity) -> &'static str {
+        match severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        }
+    }
+
+    /// Returns the byte range of the line `span` starts on, without the
+    /// newline.
+    fn line_bounds(&self, span: &Span) -> (usize, usize) {
+        let start = span.start.min(self.source.len());
+        let line_start = self.source[..start].iter()
+            .rposition(|&byte| byte == b'\n')
+            .map_or(0, |pos| pos + 1);
+        let line_end = self.source[start..].iter()
+            .position(|&byte| byte == b'\n')
+            .map_or(self.source.len(), |len| start + len);
+
+        (line_start, line_end)
+    }
+
+    pub fn render(&self, diag: &Diagnostic) -> String {
+        let style = Self::severity_style(diag.severity);
+        let mut out = String::new();
+
+        let header = match diag.code {
+            Some(code) => format!("{}[{}]", diag.severity, code),
+            None => diag.severity.to_string(),
+        };
+        out += &format!("{}{}\n",
+            self.paint(style, &header),
+            self.paint(BOLD, &format!(": {}", diag.message)));
+
+        let mut labels = vec![(&*diag.primary, true)];
+        labels.extend(diag.secondary.iter().map(|label| (label, false)));
+        labels.retain(|(label, _)| label.span.line > 0);
+        labels.sort_by_key(|(label, _)| (label.span.line, label.span.col));
+
+        let width = labels.iter()
+            .map(|(label, _)| label.span.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let gutter = " ".repeat(width);
+        let bar = self.paint(BLUE, "|");
+
+        if diag.primary.span.line > 0 {
+            out += &format!("{gutter}{} {}\n", self.paint(BLUE, "-->"), diag.primary.span);
+            out += &format!("{gutter} {bar}\n");
+        }
+
+        let mut last_line = None;
+        for (label, primary) in &labels {
+            let span = &label.span;
+            let (line_start, line_end) = self.line_bounds(span);
+            let text = String::from_utf8_lossy(&self.source[line_start..line_end]);
+            let text = text.trim_end_matches('\r');
+
+            if last_line != Some(span.line) {
+                if last_line.is_some_and(|last| last + 1 < span.line) {
+                    out += &format!("{}\n", self.paint(BLUE, "..."));
+                }
+
+                out += &format!("{} {bar} {}\n",
+                    self.paint(BLUE, &format!("{:>width$}", span.line)), text);
+                last_line = Some(span.line);
+            }
+
+            let offset = text.chars().take(span.col - 1)
+                .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+                .collect::<String>();
+            let start = span.start.min(line_end);
+            let underlined = &self.source[start..span.end.clamp(start, line_end)];
+            let len = String::from_utf8_lossy(underlined).chars().count().max(1);
+            let (mark, mark_style) = if *primary { ("^", style) } else { ("-", BLUE) };
+            let underline = format!("{} {}", mark.repeat(len), label.message);
+
+            out += &format!("{gutter} {bar} {offset}{}\n",
+                self.paint(mark_style, underline.trim_end()));
+        }
+
+        for note in &diag.notes {
+            out += &format!("{gutter} {} {}: {}\n",
+                self.paint(BLUE, "="), self.paint(BOLD, "note"), note);
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let source = b"fn main() -> i32 {\n    let a: i32 = \"x\";\n}";
+        let span = Span { file: "test.sxl".into(), line: 2, col: 18, start: 36, end: 39 };
+        let ann = Span { file: "test.sxl".into(), line: 2, col: 12, start: 30, end: 33 };
+
+        let diag = Diagnostic::error(span, "mismatched types")
+            .with_code("E0006")
+            .with_primary("expected i32, found str")
+            .with_label(ann, "expected due to this")
+            .with_note("values must match the declared type");
+
+        let out = Emitter::new(source, false).render(&diag);
+        assert_eq!(out, "\
+error[E0006]: mismatched types
+ --> test.sxl:2:18
+  |
+2 |     let a: i32 = \"x\";
+  |            --- expected due to this
+  |                  ^^^ expected i32, found str
+  = note: values must match the declared type
+");
+    }
+
+    #[test]
+    fn test_render_unicode() {
+        let source = "let größe = \"→\" + ünknown;".as_bytes();
+        let span = Span { file: "test.sxl".into(), line: 1, col: 19, start: 21, end: 29 };
+        let diag = Diagnostic::error(span, "ünknown not found in current scope");
+
+        let out = Emitter::new(source, false).render(&diag);
+        assert!(out.ends_with("\
+1 | let größe = \"→\" + ünknown;
+  |                   ^^^^^^^
+"));
+    }
+}
@@ -1,22 +1,61 @@
 use std::collections::HashMap;
-use anyhow::{Result, bail};
 
-use crate::ast::{Symbol, ValueType};
+use crate::{ast::{EnumDecl, FLOAT_TYPES, FuncDecl, INTEGER_TYPES, StructDecl, Symbol, ValueType}, diagnostic::Diagnostic, token::Span};
+
+type Result<T> = std::result::Result<T, Diagnostic>;
 
 #[derive(Debug)]
 pub struct Environment<'a> {
     parent: Option<&'a Environment<'a>>,
     symbols: HashMap<String, Symbol>,
     vtypes: HashMap<String, ()>,
+    structs: HashMap<String, StructDecl>,
+    enums: HashMap<String, EnumDecl>,
 }
 
 impl<'a> Environment<'a> {
     pub fn new() -> Self {
-        Self { 
+        Self {
             parent: None,
             symbols: HashMap::new(),
-            vtypes: HashMap::new(), 
+            vtypes: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+        }
+    }
+
+    /// Creates the top-level environment holding the builtin types and functions.
+    pub fn root() -> Self {
+        let mut env = Self::new();
+
+        for name in INTEGER_TYPES.iter().chain(&FLOAT_TYPES) {
+            env.push_vtype(ValueType::Type(name.to_string())).unwrap();
         }
+        env.push_vtype(ValueType::str()).unwrap();
+        env.push_vtype(ValueType::bool()).unwrap();
+        env.push_builtin("printf", &[("str", ValueType::str())], ValueType::void());
+        // `alloc` and `free` take a value of any type, written as `Unknown`;
+        // the type checker types calls to them.
+        env.push_builtin("alloc", &[("value", ValueType::Unknown)], ValueType::Pointer(ValueType::Unknown.into()));
+        env.push_builtin("free", &[("ptr", ValueType::Unknown)], ValueType::void());
+
+        env
+    }
+
+    fn push_builtin(&mut self, name: &str, params: &[(&str, ValueType)], vtype: ValueType) {
+        self.push_symbol(Symbol { name: name.to_owned(), vtype: ValueType::Func(FuncDecl {
+            name: name.to_owned(),
+            vtype: vtype.into(),
+            params: params.iter()
+                .map(|(name, vtype)| Symbol {
+                    name: name.to_string(),
+                    vtype: vtype.clone(),
+                    span: Span::default(),
+                    mutable: false,
+                })
+                .collect(),
+            span: Span::default(),
+        }), span: Span::default(), mutable: false }).unwrap();
     }
 
     pub fn from_parent(parent: &'a Environment) -> Self {
@@ -25,21 +64,58 @@ impl<'a> Environment<'a> {
         env
     }
 
+    pub fn get_symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.get(name)
+            .or_else(|| self.parent?.get_symbol(name))
+    }
+
     pub fn get_vtype_of(&self, name: &str) -> Option<&ValueType> {
-        self.symbols.get(name)       
-            .map(|symbol| &symbol.vtype)
-            .or_else(|| self.parent?.get_vtype_of(name))
+        self.get_symbol(name).map(|symbol| &symbol.vtype)
     }
 
     pub fn does_vtype_exist(&self, vtype: &str) -> bool {
         self.vtypes.contains_key(vtype) ||
-        self.parent.map_or(false, 
+        self.parent.is_some_and(
             |parent| parent.does_vtype_exist(vtype))
     }
 
+    pub fn get_struct(&self, name: &str) -> Option<&StructDecl> {
+        self.structs.get(name)
+            .or_else(|| self.parent?.get_struct(name))
+    }
+
+    pub fn get_enum(&self, name: &str) -> Option<&EnumDecl> {
+        self.enums.get(name)
+            .or_else(|| self.parent?.get_enum(name))
+    }
+
+    /// Returns the types a struct or enum holds by value.
+    pub fn member_vtypes(&self, name: &str) -> Vec<&ValueType> {
+        if let Some(decl) = self.get_struct(name) {
+            decl.fields.iter().map(|field| &field.vtype).collect()
+        } else if let Some(decl) = self.get_enum(name) {
+            decl.variants.iter().flat_map(|variant| &variant.fields).collect()
+        } else {
+            vec![]
+        }
+    }
+
+    /// Builds the error reported when `name` is defined twice.
+    pub fn redefinition(name: &str, span: &Span, prev: &Span) -> Diagnostic {
+        let diag = Diagnostic::error(span.clone(), format!("{} already exists", name))
+            .with_code("E0005")
+            .with_primary(format!("{name} redefined here"));
+
+        if prev.line > 0 {
+            diag.with_label(prev.clone(), format!("previous definition of {name} here"))
+        } else {
+            diag.with_note(format!("{name} is a builtin"))
+        }
+    }
+
     pub fn push_symbol(&mut self, symbol: Symbol) -> Result<()> {
-        if self.symbols.contains_key(&symbol.name) {
-            bail!("{} already exists", symbol.name);
+        if let Some(prev) = self.symbols.get(&symbol.name) {
+            return Err(Self::redefinition(&symbol.name, &symbol.span, &prev.span));
         }
 
         self.symbols.insert(symbol.name.to_owned(), symbol);
@@ -47,23 +123,31 @@ impl<'a> Environment<'a> {
     }
 
     pub fn push_vtype(&mut self, vtype: ValueType) -> Result<()> {
-        match &vtype {
-            ValueType::Type(name) => {
-                if self.vtypes.contains_key(name) {
-                    bail!("{} already exists", name);
-                }
-
-                self.vtypes.insert(name.to_owned(), ());
-            }
-            ValueType::Func(decl) => {
-                if self.vtypes.contains_key(&decl.name) {
-                    bail!("{} already exists", decl.name);
-                }
-
-                self.vtypes.insert(decl.name.to_owned(), ());
-            }
+        let name = match &vtype {
+            ValueType::Type(name) => name,
+            ValueType::Func(decl) => &decl.name,
+            ValueType::Array(..) | ValueType::Slice(_) | ValueType::Pointer(_) | ValueType::Unknown => unreachable!("cannot register {vtype}"),
         };
 
+        if self.vtypes.contains_key(name) {
+            return Err(Diagnostic::error(Span::default(), format!("type {} already exists", name))
+                .with_code("E0005"));
+        }
+
+        self.vtypes.insert(name.to_owned(), ());
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Records the fields of a struct whose name was registered with
+    /// `push_vtype`, replacing any fields recorded before.
+    pub fn push_struct(&mut self, decl: StructDecl) {
+        debug_assert!(self.vtypes.contains_key(&decl.name), "{} was not registered", decl.name);
+        self.structs.insert(decl.name.clone(), decl);
+    }
+
+    /// Records the variants of an enum, like `push_struct`.
+    pub fn push_enum(&mut self, decl: EnumDecl) {
+        debug_assert!(self.vtypes.contains_key(&decl.name), "{} was not registered", decl.name);
+        self.enums.insert(decl.name.clone(), decl);
+    }
+}
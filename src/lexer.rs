@@ -1,94 +1,194 @@
 use std::io::Write;
-use anyhow::bail;
+use std::sync::Arc;
 
-use crate::token::*;
+use crate::{diagnostic::Diagnostic, token::*};
+
+type Result<T> = std::result::Result<T, Diagnostic>;
 
 pub struct Lexer {
     input: Vec<u8>,
+    file: Arc<str>,
     pos: usize,
+    line: usize,
+    line_start: usize,
 }
 
 impl Lexer {
     pub fn new(input: Vec<u8>) -> Self {
-        Self { input, pos: 0 }
+        Self::from_file("<input>", input)
+    }
+
+    pub fn from_file(file: &str, input: Vec<u8>) -> Self {
+        Self { input, file: file.into(), pos: 0, line: 1, line_start: 0 }
     }
 
     fn read_char(&mut self) -> char {
         let ch = self.peek_char();
-        self.pos += 1;
+        if self.pos < self.input.len() {
+            self.pos += self.char_at(self.pos).map_or(1, char::len_utf8);
+        }
+        if ch == '\n' {
+            self.line += 1;
+            self.line_start = self.pos;
+        }
         ch
     }
 
+    /// Returns the character at the current position, or U+FFFD if the input
+    /// there is not valid UTF-8.
     fn peek_char(&self) -> char {
-        match self.input.get(self.pos) {
-            Some(byte) => *byte as char,
-            None => '\0',
+        self.char_at(self.pos).unwrap_or(char::REPLACEMENT_CHARACTER)
+    }
+
+    /// Decodes the character starting at byte `pos`, or returns `None` if the
+    /// bytes there are not valid UTF-8. The end of the input reads as `'\0'`.
+    fn char_at(&self, pos: usize) -> Option<char> {
+        let Some(&first) = self.input.get(pos) else { return Some('\0') };
+        let len = match first {
+            0x00..=0x7f => 1,
+            0xc2..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf4 => 4,
+            _ => return None,
+        };
+
+        let bytes = self.input.get(pos..pos + len)?;
+        str::from_utf8(bytes).ok()?.chars().next()
+    }
+
+    fn unread_char(&mut self, ch: char) {
+        self.pos -= ch.len_utf8();
+    }
+
+    /// Reports the bytes at the current position if they are not valid
+    /// UTF-8, skipping past them.
+    fn check_utf8(&mut self) -> Result<()> {
+        if self.char_at(self.pos).is_some() {
+            return Ok(());
         }
+
+        let (start, line, line_start) = (self.pos, self.line, self.line_start);
+        let err = str::from_utf8(&self.input[start..]).unwrap_err();
+        self.pos += err.error_len().unwrap_or(self.input.len() - start);
+
+        let bytes = self.input[start..self.pos].iter()
+            .map(|byte| format!("{byte:#04x}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        Err(Diagnostic::error(self.span_from(start, line, line_start), "invalid UTF-8")
+            .with_code("E0026")
+            .with_primary(format!("{bytes} is not a valid character"))
+            .with_note("source files must be encoded as UTF-8"))
+    }
+
+    /// Columns count characters rather than bytes, with any invalid UTF-8
+    /// counting once per bad sequence as it does when rendered.
+    fn span_from(&self, start: usize, line: usize, line_start: usize) -> Span {
+        Span {
+            file: self.file.clone(),
+            line,
+            col: String::from_utf8_lossy(&self.input[line_start..start]).chars().count() + 1,
+            start,
+            end: self.pos,
+        }
+    }
+
+    pub fn next_token(&mut self) -> Result<Spanned<Token>> {
+        self.skip_trivia()?;
+        self.check_utf8()?;
+        let (start, line, line_start) = (self.pos, self.line, self.line_start);
+        let token = self.read_token()?;
+
+        Ok(Spanned { node: token, span: self.span_from(start, line, line_start) })
     }
 
-    fn unread_byte(&mut self) {
-        self.pos -= 1;
+    /// Returns `compound` if an `=` follows the operator just read, else `op`.
+    fn or_assign(&mut self, op: Token, compound: Token) -> Token {
+        if self.peek_char() == '=' {
+            self.read_char();
+            compound
+        } else {
+            op
+        }
     }
 
-    pub fn next_token(&mut self) -> anyhow::Result<Token> {
-        self.skip_whitespace();
+    fn read_token(&mut self) -> Result<Token> {
         use Token::*;
 
         Ok(match self.read_char() {
-            '=' => if self.peek_char() == '=' {
+            '=' => match self.peek_char() {
+                '=' => { self.read_char(); Equal }
+                '>' => { self.read_char(); FatArrow }
+                _ => Assign,
+            }
+            '<' => match self.peek_char() {
+                '=' => { self.read_char(); Lte }
+                '<' => { self.read_char(); self.or_assign(Shl, ShlAssign) }
+                _ => Lt,
+            }
+            '>' => match self.peek_char() {
+                '=' => { self.read_char(); Gte }
+                '>' => { self.read_char(); self.or_assign(Shr, ShrAssign) }
+                _ => Gt,
+            }
+            '+' => self.or_assign(Plus, PlusAssign),
+            '-' => if self.peek_char() == '>' {
                 self.read_char();
-                Equal
+                Arrow
             } else {
-                Assign
+                self.or_assign(Minus, MinusAssign)
             }
-            '<' => if self.peek_char() == '=' {
+            '*' => self.or_assign(Asterisk, AsteriskAssign),
+            '/' => self.or_assign(Slash, SlashAssign),
+            '%' => self.or_assign(Percent, PercentAssign),
+            '~' => Tilde,
+            '^' => self.or_assign(Caret, CaretAssign),
+            '!' => if self.peek_char() == '=' {
                 self.read_char();
-                Lte
+                NotEqual
             } else {
-                Lt
+                Bang
             }
-            '>' => if self.peek_char() == '=' {
+            '.' => if self.peek_char() == '.' {
                 self.read_char();
-                Gte
+                DotDot
             } else {
-                Gt
+                Dot
             }
-            '+' => Plus,
-            '-' => if self.peek_char() == '>' {
+            '&' => if self.peek_char() == '&' {
                 self.read_char();
-                Arrow
+                And
             } else {
-                Minus
+                self.or_assign(Ampersand, AmpersandAssign)
             }
-            '*' => Asterisk,
-            '/' => if self.peek_char() == '/' {
+            '|' => if self.peek_char() == '|' {
                 self.read_char();
-                self.skip_comment();
-                self.next_token()?
+                Or
             } else {
-                Slash
+                self.or_assign(Pipe, PipeAssign)
             }
-            '!' => if self.peek_char() == '=' {
+            ',' => Comma,
+            ':' => if self.peek_char() == ':' {
                 self.read_char();
-                NotEqual
+                ColonColon
             } else {
-                Bang
+                Colon
             }
-            ',' => Comma,
-            ':' => Colon,
             ';' => Semicolon,
             '(' => LParen,
+            '[' => LBracket,
+            ']' => RBracket,
             ')' => RParen,
             '{' => LBrace,
             '}' => RBrace,
-            '"' => Token::String(self.read_string()?.to_string()),
+            '"' => Token::String(self.read_string()?),
             '\0' => Eof,
             ch => if ch.is_ascii_digit() {
-                self.unread_byte();
-                Int(self.read_int().to_string())
-            } else if Self::is_ident_char(ch) {
-                self.unread_byte();
-                let ident = self.read_ident()?;
+                self.unread_char(ch);
+                self.read_number()
+            } else if Self::is_ident_start(ch) {
+                self.unread_char(ch);
+                let ident = self.read_ident();
                 Token::from_symbol(ident)
             } else {
                 Illegal
@@ -96,54 +196,213 @@ impl Lexer {
         })
     }
 
+    fn skip_trivia(&mut self) -> Result<()> {
+        loop {
+            self.skip_whitespace();
+
+            if self.peek_char() == '/' && self.input.get(self.pos + 1) == Some(&b'/') {
+                self.skip_comment()?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
     fn skip_whitespace(&mut self) {
         while " \t\n\r".contains(self.peek_char()) {
             self.read_char();
         }
     }
 
-    fn skip_comment(&mut self) {
+    /// Skips to the end of the line, reporting the first invalid UTF-8 in it
+    /// once the whole comment is skipped.
+    fn skip_comment(&mut self) -> Result<()> {
+        let mut result = Ok(());
+
         while !"\0\n".contains(self.peek_char()) {
-            self.read_char();
+            match self.check_utf8() {
+                Ok(()) => { self.read_char(); }
+                Err(diag) => if result.is_ok() { result = Err(diag) },
+            }
         }
+
+        result
+    }
+
+    /// Identifiers follow Unicode's XID rules, with `_` also allowed first.
+    fn is_ident_start(ch: char) -> bool {
+        ch == '_' || unicode_ident::is_xid_start(ch)
     }
 
-    fn is_ident_char(ch: char) -> bool {
-        ch.is_ascii_alphabetic() || ch.is_ascii_digit() || ch == '_'
+    fn is_ident_continue(ch: char) -> bool {
+        unicode_ident::is_xid_continue(ch)
     }
 
-    fn read_ident(&mut self) -> anyhow::Result<&str> {
+    fn read_ident(&mut self) -> &str {
         let start = self.pos;
 
-        while Self::is_ident_char(self.peek_char()) {
+        while Self::is_ident_continue(self.peek_char()) {
             self.read_char();
         }
 
-        Ok(str::from_utf8(&self.input[start..self.pos])?)
+        str::from_utf8(&self.input[start..self.pos]).unwrap()
     }
 
-    fn read_int(&mut self) -> &str {
-        let start = self.pos;
+    /// Looks `offset` bytes ahead for an ASCII character, as number literals
+    /// need. Anything else reads as `'\0'`.
+    fn byte_at(&self, offset: usize) -> char {
+        self.input.get(self.pos + offset)
+            .filter(|byte| byte.is_ascii())
+            .map_or('\0', |byte| *byte as char)
+    }
 
-        while self.peek_char().is_ascii_digit() {
+    fn skip_alphanumeric(&mut self) {
+        while self.peek_char().is_ascii_alphanumeric() || self.peek_char() == '_' {
             self.read_char();
         }
+    }
 
-        str::from_utf8(&self.input[start..self.pos]).unwrap()
+    fn skip_digits(&mut self) {
+        while self.peek_char().is_ascii_digit() || self.peek_char() == '_' {
+            self.read_char();
+        }
     }
 
-    fn read_string(&mut self) -> anyhow::Result<&str> {
+    /// Reads an integer or float literal, including any radix prefix, `_`
+    /// separators, fraction, exponent and type suffix. The parser validates
+    /// what was read.
+    fn read_number(&mut self) -> Token {
         let start = self.pos;
+        let mut is_float = false;
 
-        while !['"', '\0'].contains(&self.peek_char()) {
-            self.read_char();
+        if self.peek_char() == '0' && "xob".contains(self.byte_at(1)) {
+            self.skip_alphanumeric();
+        } else {
+            self.skip_digits();
+
+            // `1..2` is a range, not a float.
+            if self.peek_char() == '.' && self.byte_at(1).is_ascii_digit() {
+                is_float = true;
+                self.read_char();
+                self.skip_digits();
+            }
+
+            let sign = if "+-".contains(self.byte_at(1)) { 1 } else { 0 };
+            if "eE".contains(self.peek_char()) && self.byte_at(1 + sign).is_ascii_digit() {
+                is_float = true;
+                self.read_char();
+                if sign == 1 { self.read_char(); }
+                self.skip_digits();
+            }
+
+            is_float |= self.peek_char() == 'f';
+            self.skip_alphanumeric();
         }
 
-        if self.read_char() != '"' {
-            bail!("Expected \" at the end of the string literal");
+        let lit = str::from_utf8(&self.input[start..self.pos]).unwrap().to_string();
+        if is_float { Token::Float(lit) } else { Token::Int(lit) }
+    }
+
+    /// Reads a string literal once its opening quote has been read, decoding
+    /// its escape sequences.
+    fn read_string(&mut self) -> Result<String> {
+        let start = self.pos;
+        let (line, line_start) = (self.line, self.line_start);
+        let mut value = String::new();
+
+        loop {
+            let result = match self.peek_char() {
+                '"' => break,
+                '\\' => self.read_escape(&mut value),
+                _ if self.pos >= self.input.len() => {
+                    let span = self.span_from(start - 1, line, line_start);
+                    return Err(Diagnostic::error(span, "unterminated string literal")
+                        .with_code("E0002")
+                        .with_primary("expected \" at the end of the string literal"));
+                }
+                _ => self.check_utf8().map(|()| value.push(self.read_char())),
+            };
+
+            if let Err(diag) = result {
+                self.skip_string();
+                return Err(diag);
+            }
         }
+        self.read_char(); // "
 
-        Ok(str::from_utf8(&self.input[start..self.pos - 1])?)
+        Ok(value)
+    }
+
+    /// Reads an escape sequence like `\n`, `\x41` or `\u{1F600}`, appending the
+    /// character it stands for.
+    fn read_escape(&mut self, value: &mut String) -> Result<()> {
+        let (start, line, line_start) = (self.pos, self.line, self.line_start);
+        self.read_char(); // \
+
+        let ch = match self.read_char() {
+            'n' => Some('\n'),
+            'r' => Some('\r'),
+            't' => Some('\t'),
+            '0' => Some('\0'),
+            '\\' => Some('\\'),
+            '"' => Some('"'),
+            '\'' => Some('\''),
+            'x' => {
+                let digits = self.read_hex_digits(2);
+                u8::from_str_radix(&digits, 16).ok()
+                    .filter(|&value| digits.len() == 2 && value <= 0x7f)
+                    .map(char::from)
+            }
+            'u' if self.peek_char() == '{' => {
+                self.read_char();
+                let digits = self.read_hex_digits(6);
+                if self.peek_char() == '}' {
+                    self.read_char();
+                    u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32)
+                } else {
+                    None
+                }
+            }
+            _ => {
+                let span = self.span_from(start, line, line_start);
+                return Err(Diagnostic::error(span, "unknown character escape")
+                    .with_code("E0002")
+                    .with_primary("unknown escape")
+                    .with_note("the escapes are \\n \\r \\t \\0 \\\\ \\\" \\' \\x7f and \\u{10ffff}"));
+            }
+        };
+
+        let Some(ch) = ch else {
+            let span = self.span_from(start, line, line_start);
+            return Err(Diagnostic::error(span, "invalid character escape")
+                .with_code("E0002")
+                .with_primary("not a valid character")
+                .with_note("\\x takes two hex digits up to 7f, \\u{...} up to six hex digits of a Unicode scalar value"));
+        };
+
+        value.push(ch);
+        Ok(())
+    }
+
+    /// Skips the rest of a string literal after a bad escape, so lexing picks
+    /// up after it instead of in the middle.
+    fn skip_string(&mut self) {
+        while self.pos < self.input.len() {
+            match self.read_char() {
+                '"' => break,
+                '\\' => { self.read_char(); }
+                _ => (),
+            }
+        }
+    }
+
+    /// Reads up to `max` hex digits.
+    fn read_hex_digits(&mut self, max: usize) -> String {
+        let mut digits = String::new();
+        while digits.len() < max && self.peek_char().is_ascii_hexdigit() {
+            digits.push(self.read_char());
+        }
+        digits
     }
 }
 
@@ -162,8 +421,8 @@ pub fn repl() {
 
         loop {
             let tok = lexer.next_token().unwrap();
-            if tok == Token::Eof { break; }
-            println!("{tok}");
+            if tok.node == Token::Eof { break; }
+            println!("{} {}", tok.span, tok.node);
         }
     }
 }
@@ -204,12 +463,184 @@ mod tests {
         let mut lexer = Lexer::new(input.to_vec());
         let mut out = vec![];
 
-        let mut tok = lexer.next_token().unwrap();
+        let mut tok = lexer.next_token().unwrap().node;
         while tok != Eof {
             out.push(tok);
-            tok = lexer.next_token().unwrap();
+            tok = lexer.next_token().unwrap().node;
         }
 
         assert_eq!(ok, out);
     }
+
+    #[test]
+    fn test_operators_and_numbers() {
+        let input = b"a % b & c | d ^ ~e << 1 >> 2 && f || g <= h >= i;
+        += -= *= /= %= &= |= ^= <<= >>= -> :: => : = [ ]
+        3.14 0..10 1e-9 2.5f32 7f64 0xfe 1.x";
+
+        use Token::*;
+        let ok = vec![
+            Ident("a".to_string()),
+            Percent,
+            Ident("b".to_string()),
+            Ampersand,
+            Ident("c".to_string()),
+            Pipe,
+            Ident("d".to_string()),
+            Caret,
+            Tilde,
+            Ident("e".to_string()),
+            Shl,
+            Int("1".to_string()),
+            Shr,
+            Int("2".to_string()),
+            And,
+            Ident("f".to_string()),
+            Or,
+            Ident("g".to_string()),
+            Lte,
+            Ident("h".to_string()),
+            Gte,
+            Ident("i".to_string()),
+            Semicolon,
+            PlusAssign,
+            MinusAssign,
+            AsteriskAssign,
+            SlashAssign,
+            PercentAssign,
+            AmpersandAssign,
+            PipeAssign,
+            CaretAssign,
+            ShlAssign,
+            ShrAssign,
+            Arrow,
+            ColonColon,
+            FatArrow,
+            Colon,
+            Assign,
+            LBracket,
+            RBracket,
+            Float("3.14".to_string()),
+            Int("0".to_string()),
+            DotDot,
+            Int("10".to_string()),
+            Float("1e-9".to_string()),
+            Float("2.5f32".to_string()),
+            Float("7f64".to_string()),
+            Int("0xfe".to_string()),
+            Int("1".to_string()),
+            Dot,
+            Ident("x".to_string()),
+        ];
+
+        let mut lexer = Lexer::new(input.to_vec());
+        let mut out = vec![];
+
+        let mut tok = lexer.next_token().unwrap().node;
+        while tok != Eof {
+            out.push(tok);
+            tok = lexer.next_token().unwrap().node;
+        }
+
+        assert_eq!(ok, out);
+    }
+
+    #[test]
+    fn test_lexer_spans() {
+        let input = b"let a = 1; // comment
+  return a;";
+
+        let mut lexer = Lexer::from_file("test.sxl", input.to_vec());
+        let mut spans = vec![];
+
+        loop {
+            let tok = lexer.next_token().unwrap();
+            if tok.node == Token::Eof { break; }
+            spans.push((tok.node, tok.span.line, tok.span.col, tok.span.end - tok.span.start));
+        }
+
+        use Token::*;
+        assert_eq!(spans, vec![
+            (Let, 1, 1, 3),
+            (Ident("a".to_string()), 1, 5, 1),
+            (Assign, 1, 7, 1),
+            (Int("1".to_string()), 1, 9, 1),
+            (Semicolon, 1, 10, 1),
+            (Return, 2, 3, 6),
+            (Ident("a".to_string()), 2, 10, 1),
+            (Semicolon, 2, 11, 1),
+        ]);
+        assert_eq!(format!("{}", Span { line: 2, col: 3, file: "test.sxl".into(), ..Default::default() }), "test.sxl:2:3");
+    }
+
+    #[test]
+    fn test_unicode() {
+        let input = "let größe = \"→ 世界\"; _x1 ÿ\n// ünïcode\nπ".as_bytes();
+        let mut lexer = Lexer::new(input.to_vec());
+        let mut tokens = vec![];
+
+        loop {
+            let tok = lexer.next_token().unwrap();
+            if tok.node == Token::Eof { break; }
+            tokens.push((tok.node, tok.span.col, tok.span.end - tok.span.start));
+        }
+
+        use Token::*;
+        assert_eq!(tokens, vec![
+            (Let, 1, 3),
+            (Ident("größe".to_string()), 5, 7),
+            (Assign, 11, 1),
+            (String("→ 世界".to_string()), 13, 12),
+            (Semicolon, 19, 1),
+            (Ident("_x1".to_string()), 21, 3),
+            (Ident("ÿ".to_string()), 25, 2),
+            (Ident("π".to_string()), 1, 2),
+        ]);
+
+        // Combining marks and symbols can't start an identifier.
+        assert_eq!(Lexer::new("\u{301}a".as_bytes().to_vec()).next_token().unwrap().node, Illegal);
+        assert_eq!(Lexer::new("😀".as_bytes().to_vec()).next_token().unwrap().node, Illegal);
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let input = b"a \xff b // \xc3 c\n\"x\xe2\x82\" d \xf0\x9f";
+        let mut lexer = Lexer::new(input.to_vec());
+        let mut results = vec![];
+
+        loop {
+            match lexer.next_token() {
+                Ok(tok) if tok.node == Token::Eof => break,
+                Ok(tok) => results.push(Ok(tok.node)),
+                Err(diag) => results.push(Err((diag.code, diag.primary.span.line, diag.primary.span.col, diag.primary.message))),
+            }
+        }
+
+        use Token::*;
+        assert_eq!(results, vec![
+            Ok(Ident("a".to_string())),
+            Err((Some("E0026"), 1, 3, "0xff is not a valid character".to_string())),
+            Ok(Ident("b".to_string())),
+            Err((Some("E0026"), 1, 10, "0xc3 is not a valid character".to_string())),
+            Err((Some("E0026"), 2, 3, "0xe2 0x82 is not a valid character".to_string())),
+            Ok(Ident("d".to_string())),
+            Err((Some("E0026"), 2, 8, "0xf0 0x9f is not a valid character".to_string())),
+        ]);
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let input = br#""a\n\t\"b\\\x41\u{e9}\u{1F600}\0" "\q x" "\x80" "\u{d800}" "\u{41" ok"#;
+        let mut lexer = Lexer::new(input.to_vec());
+
+        assert_eq!(lexer.next_token().unwrap().node, Token::String("a\n\t\"b\\A\u{e9}\u{1F600}\0".to_string()));
+        for _ in 0..4 {
+            let diag = lexer.next_token().unwrap_err();
+            assert_eq!(diag.code, Some("E0002"));
+        }
+        assert_eq!(lexer.next_token().unwrap().node, Token::Ident("ok".to_string()));
+
+        let diag = Lexer::new(b"\"abc".to_vec()).next_token().unwrap_err();
+        assert_eq!(diag.message, "unterminated string literal");
+    }
 }
@@ -1,18 +1,28 @@
-use std::process::Command;
+use std::{io::IsTerminal, process::Command};
 use anyhow::{Context, bail};
 
-use crate::{compiler::Compiler, lexer::Lexer, parser::Parser};
+use crate::{compiler::Compiler, diagnostic::Emitter, lexer::Lexer, parser::Parser, typeck::TypeChecker};
 
 mod ast;
 mod compiler;
+mod diagnostic;
 mod lexer;
 mod parser;
 mod token;
 mod environment;
+mod typeck;
+
+/// Settings for compiling a file.
+struct Options {
+    /// Check array indices at runtime, `--no-bounds-checks` to disable.
+    bounds_checks: bool,
+    /// Track allocations to report leaks and double frees, `--debug-alloc`.
+    debug_alloc: bool,
+}
 
 enum Mode {
-    Compile { file: String },
-    CompileAndRun { file: String },
+    Compile { file: String, options: Options },
+    CompileAndRun { file: String, options: Options },
     LexerRepl,
     ParserRepl,
 }
@@ -21,11 +31,14 @@ impl Mode {
     fn from_args(args: std::env::Args) -> anyhow::Result<Self> {
         let mut file = None;
         let mut run_mode = false;
+        let mut options = Options { bounds_checks: true, debug_alloc: false };
 
         for arg in args.skip(1) {
             match arg.as_str() {
                 "--compile" => (),
                 "--run" => run_mode = true,
+                "--no-bounds-checks" => options.bounds_checks = false,
+                "--debug-alloc" => options.debug_alloc = true,
                 "--lexer-repl" => return Ok(Mode::LexerRepl),
                 "--parser-repl" => return Ok(Mode::ParserRepl),
                 arg => file = Some(arg.to_string()),
@@ -37,40 +50,59 @@ impl Mode {
         };
 
         Ok(if run_mode {
-            Mode::CompileAndRun { file }
+            Mode::CompileAndRun { file, options }
         } else {
-            Mode::Compile { file }
+            Mode::Compile { file, options }
         })
     }
 
-    fn compile_file(&self, file: &str) -> anyhow::Result<()> {
+    fn compile_file(file: &str, options: &Options) -> anyhow::Result<()> {
         let input = std::fs::read(file)?;
-        let lexer = Lexer::new(input);
-        let mut parser = Parser::new(lexer)?;
+        let emitter = Emitter::new(&input, std::io::stderr().is_terminal());
+        let lexer = Lexer::from_file(file, input.clone());
+
+        let result = Parser::new(lexer)
+            .map_err(|diag| vec![diag])
+            .and_then(|mut parser| parser.parse_program())
+            .and_then(|mut program| {
+                let warnings = TypeChecker::new().check_program(&mut program)?;
+                Ok((program, warnings))
+            });
 
-        match parser.parse_program() {
-            Ok(program) => {
-                let compiler = Compiler::new();
+        match result {
+            Ok((program, warnings)) => {
+                for diag in &warnings {
+                    eprintln!("{}", emitter.render(diag));
+                }
+
+                let compiler = Compiler::new()
+                    .with_bounds_checks(options.bounds_checks)
+                    .with_debug_alloc(options.debug_alloc);
                 let output = compiler.compile_program(program);
                 std::fs::write(format!("{file}.c"), output)?;
                 Ok(())
             }
-            Err(err) => {
-                eprintln!("{}", err);
-                bail!("Compilation failed");
+            Err(diags) => {
+                for diag in &diags {
+                    eprintln!("{}", emitter.render(diag));
+                }
+
+                let errors = diags.iter().filter(|diag| diag.is_error()).count();
+                bail!("Compilation failed due to {} previous error{}",
+                    errors, if errors == 1 { "" } else { "s" });
             },
         }
     }
 
     fn run(self) -> anyhow::Result<()> {
         match self {
-            Mode::Compile { ref file } => self.compile_file(file)?,
-            Mode::CompileAndRun { ref file } => {
-                self.compile_file(file)?;
+            Mode::Compile { file, options } => Self::compile_file(&file, &options)?,
+            Mode::CompileAndRun { file, options } => {
+                Self::compile_file(&file, &options)?;
                 let exe = if file.ends_with(".sxl") {
                     &file[..file.len()-4]
                 } else {
-                    file
+                    &file
                 };
 
                 Command::new("clang")
@@ -1,48 +1,133 @@
-use std::io::Write;
-use anyhow::{Result, anyhow, bail};
-use crate::{ast::{BlockStmt, ExprKind, Expression, FuncDecl, Program, Statement, Symbol, ValueType}, environment::Environment, lexer::Lexer, token::Token};
+use std::io::{IsTerminal, Write};
+use crate::{ast::{BlockStmt, EnumDecl, ExprKind, Expression, FLOAT_TYPES, FuncDecl, INTEGER_TYPES, MatchArm, Pattern, Program, Statement, StmtKind, StructDecl, Symbol, ValueType, Variant}, diagnostic::{Diagnostic, Emitter}, lexer::Lexer, token::{Span, Spanned, Token}};
+
+type Result<T> = std::result::Result<T, Diagnostic>;
+
+/// Parsing stops once this many errors have been reported.
+const MAX_ERRORS: usize = 32;
 
 pub struct Parser {
     lexer: Lexer,
     peek_token: Token,
+    peek_span: Span,
+    token: Token,
+    span: Span,
+    diagnostics: Vec<Diagnostic>,
+    /// Set while parsing `if` and loop headers, where `name {` starts the
+    /// body rather than a struct literal.
+    no_struct_literals: bool,
 }
 
 #[derive(PartialEq, PartialOrd)]
 pub enum BindingPower {
     Lowest,
     Assign,
+    LogicalOr,
+    LogicalAnd,
+    BitOr,
+    BitXor,
+    BitAnd,
     Equals,
+    Shift,
     Sum,
     Product,
+    Cast,
     Unary,
     Call,
 }
 
 impl Parser {
-    pub fn new(mut lexer: Lexer) -> anyhow::Result<Self> {
+    pub fn new(mut lexer: Lexer) -> Result<Self> {
+        let peek = lexer.next_token()?;
+
         Ok(Self {
-            peek_token: lexer.next_token()?,
+            peek_token: peek.node,
+            token: Token::Illegal,
+            span: peek.span.clone(),
+            peek_span: peek.span,
             lexer,
+            diagnostics: vec![],
+            no_struct_literals: false,
         })
     }
 
-    fn next_token(&mut self) -> anyhow::Result<Token> {
-        Ok(std::mem::replace(&mut self.peek_token, self.lexer.next_token()?))
+    /// Consumes the peeked token. It is available with its span in
+    /// `self.token` and `self.span` afterwards.
+    fn next_token(&mut self) -> Result<Token> {
+        let next = self.lexer.next_token()?;
+        self.span = std::mem::replace(&mut self.peek_span, next.span);
+        self.token = std::mem::replace(&mut self.peek_token, next.node);
+        Ok(self.token.clone())
     }
 
-    fn expect_peek(&mut self, token: &Token) -> anyhow::Result<()> {
+    fn expect_peek(&mut self, token: &Token) -> Result<()> {
         if self.peek_token == *token {
             self.next_token()?;
             Ok(())
         } else {
-            anyhow::bail!("expected {} but got {}", token, self.peek_token)
+            Err(Diagnostic::error(self.peek_span.clone(),
+                    format!("expected {} but got {}", token, self.peek_token))
+                .with_code("E0001")
+                .with_primary(format!("expected {token}")))
         }
     }
 
-    fn expect_ident(&mut self) -> anyhow::Result<String> {
+    fn expect_ident(&mut self) -> Result<String> {
         match self.next_token()? {
             Token::Ident(value) => Ok(value),
-            token => anyhow::bail!("expected identifier but got {}", token)
+            token => Err(Diagnostic::error(self.span.clone(),
+                    format!("expected identifier but got {}", token))
+                .with_code("E0001")
+                .with_primary("expected identifier")),
+        }
+    }
+
+    /// Records a diagnostic, returning false once the error limit is reached.
+    fn report(&mut self, diag: Diagnostic) -> bool {
+        self.diagnostics.push(diag);
+
+        let errors = self.diagnostics.iter().filter(|diag| diag.is_error()).count();
+        if errors == MAX_ERRORS {
+            let last = self.diagnostics.last_mut().unwrap();
+            *last = last.clone().with_note(format!("aborting after {MAX_ERRORS} errors"));
+        }
+
+        errors < MAX_ERRORS
+    }
+
+    fn error_limit_reached(&self) -> bool {
+        self.diagnostics.iter().filter(|diag| diag.is_error()).count() >= MAX_ERRORS
+    }
+
+    /// Skips tokens after a failed statement until a point where parsing can
+    /// resume: just past a `;`, or before a `}`, `fn` or `let`. Nested blocks
+    /// are skipped as a whole so their contents can't end the outer one.
+    fn synchronize(&mut self) {
+        if self.token == Token::Semicolon {
+            return;
+        }
+
+        let mut depth = 0;
+
+        loop {
+            match self.peek_token {
+                Token::Eof => return,
+                Token::Semicolon if depth == 0 => {
+                    if let Err(diag) = self.next_token() {
+                        self.report(diag);
+                    }
+                    return;
+                }
+                Token::RBrace | Token::Fn | Token::Let | Token::Struct | Token::Enum
+                    if depth == 0 => return,
+                Token::LBrace => depth += 1,
+                Token::RBrace => depth -= 1,
+                _ => (),
+            }
+
+            if let Err(diag) = self.next_token() {
+                self.report(diag);
+            }
         }
     }
 
@@ -53,9 +138,16 @@ impl Parser {
             Token::Equal | Token::NotEqual | Token::Lt | Token::Gt
             | Token::Lte | Token::Gte => Equals,
             Token::Plus | Token::Minus => Sum,
-            Token::Asterisk | Token::Slash => Product,
-            Token::Assign => Assign,
-            Token::LParen => Call,
+            Token::Asterisk | Token::Slash | Token::Percent => Product,
+            Token::Shl | Token::Shr => Shift,
+            Token::Ampersand => BitAnd,
+            Token::Caret => BitXor,
+            Token::Pipe => BitOr,
+            Token::As => Cast,
+            Token::And => LogicalAnd,
+            Token::Or => LogicalOr,
+            token if token.is_assign() => Assign,
+            Token::LParen | Token::LBracket | Token::Dot => Call,
             _ => Lowest,
         }
     }
@@ -64,13 +156,26 @@ impl Parser {
         Self::get_binding_power(&self.peek_token)
     }
 
-    fn parse_expression(&mut self, bpow: BindingPower, env: &Environment) -> anyhow::Result<Expression> {
+    fn parse_expression(&mut self, bpow: BindingPower) -> Result<Expression> {
         let mut left = match self.next_token()? {
-            Token::Ident(name) => self.parse_ident(name, env)?,
+            Token::Ident(name) if self.peek_token == Token::LBrace && !self.no_struct_literals =>
+                self.parse_struct_literal(name)?,
+            Token::Ident(name) if self.peek_token == Token::ColonColon =>
+                self.parse_variant_expression(name)?,
+            Token::Ident(name) => Expression::new(ExprKind::Ident { value: name }, self.span.clone()),
             Token::Int(lit) => self.parse_int(&lit)?,
-            Token::String(lit) => self.parse_string(lit)?,
-            op @ (Token::Minus | Token::Bang) => self.parse_unary_expression(op, env)?,
-            token => bail!("invalid prefix operator {}", token),
+            Token::Float(lit) => self.parse_float(&lit)?,
+            Token::String(lit) => Expression::new(ExprKind::String { value: lit }, self.span.clone()),
+            Token::True => Expression::new(ExprKind::Bool { value: true }, self.span.clone()),
+            Token::False => Expression::new(ExprKind::Bool { value: false }, self.span.clone()),
+            op @ (Token::Minus | Token::Bang | Token::Tilde | Token::Ampersand | Token::Asterisk) =>
+                self.parse_unary_expression(op)?,
+            Token::LParen => self.parse_grouped_expression()?,
+            Token::LBracket => self.parse_array_literal()?,
+            token => return Err(Diagnostic::error(self.span.clone(),
+                    format!("invalid prefix operator {}", token))
+                .with_code("E0001")
+                .with_primary("expected expression")),
         };
 
         while self.peek_token != Token::Semicolon
@@ -78,9 +183,14 @@ impl Parser {
             left = match self.peek_token {
                 Token::Equal | Token::NotEqual | Token::Lt | Token::Lte
                 | Token::Gt | Token::Gte | Token::Plus | Token::Minus
-                | Token::Asterisk | Token::Slash
-                | Token::Assign => self.parse_binary_expression(left, env)?,
-                Token::LParen => self.parse_call_expression(left, env)?,
+                | Token::Asterisk | Token::Slash | Token::Percent | Token::And
+                | Token::Or | Token::Ampersand | Token::Pipe | Token::Caret
+                | Token::Shl | Token::Shr => self.parse_binary_expression(left)?,
+                ref token if token.is_assign() => self.parse_assign_expression(left)?,
+                Token::LParen => self.parse_call_expression(left)?,
+                Token::Dot => self.parse_field_expression(left)?,
+                Token::LBracket => self.parse_index_expression(left)?,
+                Token::As => self.parse_cast_expression(left)?,
                 _ => return Ok(left),
             }
         }
@@ -88,97 +198,269 @@ impl Parser {
         Ok(left)
     }
 
-    fn parse_ident(&self, name: String, env: &Environment) -> anyhow::Result<Expression> {
-        let vtype = env
-            .get_vtype_of(&name)
-            .ok_or_else(|| anyhow!("{} not found in current scope", name))?
-            .clone();
+    /// Parses literals like `42`, `0xff`, `0b1010_1010` or `5_000_000_000i64`.
+    fn parse_int(&self, lit: &str) -> Result<Expression> {
+        let invalid = |message: String| Diagnostic::error(self.span.clone(),
+                format!("invalid integer literal {}", lit))
+            .with_code("E0003")
+            .with_primary(message);
+
+        let (radix, digits) = match lit.get(..2) {
+            Some("0x") => (16, &lit[2..]),
+            Some("0o") => (8, &lit[2..]),
+            Some("0b") => (2, &lit[2..]),
+            _ => (10, lit),
+        };
 
-        Ok(Expression {
-            kind: ExprKind::Ident { value: name },
-            vtype,
-        })
+        // Hex digits never include `i` or `u`, so a suffix starts at either.
+        let (digits, suffix) = match digits.find(['i', 'u']) {
+            Some(pos) => (&digits[..pos], Some(&digits[pos..])),
+            None => (digits, None),
+        };
+
+        let suffix = match suffix {
+            Some(suffix) if INTEGER_TYPES.contains(&suffix) => Some(ValueType::Type(suffix.to_owned())),
+            Some(suffix) => return Err(invalid(format!("unknown suffix {suffix}"))
+                .with_note(format!("valid suffixes are {}", INTEGER_TYPES.join(", ")))),
+            None => None,
+        };
+
+        let digits = digits.replace('_', "");
+        if digits.is_empty() {
+            return Err(invalid("no digits".to_owned()));
+        }
+
+        let value = u64::from_str_radix(&digits, radix)
+            .map_err(|err| invalid(format!("{err}")))?;
+
+        Ok(Expression::new(ExprKind::Int { value, suffix }, self.span.clone()))
     }
 
-    fn parse_int(&self, lit: &str) -> anyhow::Result<Expression> {
-        Ok(Expression { 
-            kind: ExprKind::Int { value: lit.parse()? }, 
-            vtype: ValueType::i32(),
-        })
+    /// Parses literals like `2.75`, `1e-9`, `6.022_140e23` or `0.5f32`.
+    fn parse_float(&self, lit: &str) -> Result<Expression> {
+        let invalid = |message: String| Diagnostic::error(self.span.clone(),
+                format!("invalid float literal {}", lit))
+            .with_code("E0003")
+            .with_primary(message);
+
+        // The lexer only reads an `e` as an exponent when digits follow it,
+        // so any other letter starts the suffix.
+        let bytes = lit.as_bytes();
+        let suffix_start = (0..bytes.len()).find(|&i| bytes[i].is_ascii_alphabetic()
+            && !(matches!(bytes[i], b'e' | b'E')
+                && bytes.get(i + 1).is_some_and(|next| next.is_ascii_digit() || matches!(next, b'+' | b'-'))));
+
+        let (digits, suffix) = match suffix_start {
+            Some(pos) => (&lit[..pos], Some(&lit[pos..])),
+            None => (lit, None),
+        };
+
+        let suffix = match suffix {
+            Some(suffix) if FLOAT_TYPES.contains(&suffix) => Some(ValueType::Type(suffix.to_owned())),
+            Some(suffix) => return Err(invalid(format!("unknown suffix {suffix}"))
+                .with_note(format!("valid suffixes are {}", FLOAT_TYPES.join(", ")))),
+            None => None,
+        };
+
+        let value = digits.replace('_', "").parse::<f64>()
+            .map_err(|err| invalid(format!("{err}")))?;
+
+        if value.is_infinite() {
+            return Err(invalid("too large for f64".to_owned()));
+        }
+
+        Ok(Expression::new(ExprKind::Float { value, suffix }, self.span.clone()))
     }
 
-    fn parse_string(&self, lit: String) -> Result<Expression> {
-        Ok(Expression { 
-            kind: ExprKind::String { value: lit }, 
-            vtype: ValueType::str(),
-        })
+    fn parse_unary_expression(&mut self, op: Token) -> Result<Expression> {
+        let start = self.span.clone();
+        let right = self.parse_expression(BindingPower::Unary)?;
+
+        Ok(Expression::new(ExprKind::Unary {
+            op,
+            right: right.into(),
+        }, start.to(&self.span)))
     }
 
-    fn parse_unary_expression(&mut self, op: Token, env: &Environment) -> anyhow::Result<Expression> {
-        let right = self.parse_expression(BindingPower::Unary, env)?;
+    /// Parses `(expr)`. No node is created for the parentheses; they only
+    /// shape the tree and widen the span.
+    fn parse_grouped_expression(&mut self) -> Result<Expression> {
+        let start = self.span.clone();
+        let mut expr = self.allowing_struct_literals(true,
+            |parser| parser.parse_expression(BindingPower::Lowest))?;
+        self.expect_peek(&Token::RParen)?;
 
-        Ok(match &right.vtype {
-            vtype @ ValueType::Type(vtypes) 
-                if vtypes == "i32" => Expression {
-                    kind: ExprKind::Unary {
-                        op,
-                        right: right.into(),
-                    },
-                    vtype: ValueType::i32(),
-                },
-            vtype => bail!("{} is not supported for {:?}", op, vtype),
-        })
+        expr.span = start.to(&self.span);
+        Ok(expr)
     }
 
-    fn parse_binary_expression(&mut self, left: Expression, env: &Environment) -> anyhow::Result<Expression> {
-        let op = self.next_token()?;
-        let bpow = Parser::get_binding_power(&op);
-        let right = self.parse_expression(bpow, env)?;
-
-        Ok(match (&left.vtype, &right.vtype) {
-            (ValueType::Type(left_vtypes), ValueType::Type(right_vtypes))
-                if left_vtypes == "i32" && right_vtypes == "i32" => Expression {
-                    kind: ExprKind::Binary {
-                        op,
-                        left: left.into(),
-                        right: right.into(),
-                    },
-                    vtype: ValueType::i32(),
-                },
-            vtype => bail!("{} is not supported for {:?}", op, vtype),
-        })
+    /// Runs `parse` with struct literals allowed or not, restoring the outer
+    /// setting afterwards.
+    fn allowing_struct_literals<T>(&mut self, allowed: bool, parse: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let outer = std::mem::replace(&mut self.no_struct_literals, !allowed);
+        let result = parse(self);
+        self.no_struct_literals = outer;
+        result
     }
 
-    fn parse_call_expression(&mut self, left: Expression, env: &Environment) -> anyhow::Result<Expression> {
-        let args = self.parse_call_arguments(env)?;
+    /// Parses the condition of an `if` or `while`, or a `for` range bound.
+    fn parse_header_expression(&mut self) -> Result<Expression> {
+        self.allowing_struct_literals(false, |parser| parser.parse_expression(BindingPower::Lowest))
+    }
 
-        Ok(match &left.vtype {
-            ValueType::Func(decl) => {
-                if args.len() != decl.params.len() {
-                    bail!("{:?} expected {} arguments but got {}",
-                        left, decl.params.len(), args.len());
-                }
+    /// Parses `Name { field: value, ... }` once `Name` has been read.
+    fn parse_struct_literal(&mut self, name: String) -> Result<Expression> {
+        let name = Spanned { node: name, span: self.span.clone() };
+        self.next_token()?; // {
+        let mut fields = vec![];
 
-                for (arg, param) in args.iter().zip(decl.params.iter()) {
-                    if arg.vtype != param.vtype {
-                        bail!("parameter {} expected something of type {:?} but got {:?}",
-                            param.name, param.vtype, arg);
-                    }
-                }
+        while self.peek_token != Token::RBrace {
+            let field = self.expect_ident()?;
+            let field = Spanned { node: field, span: self.span.clone() };
+            self.expect_peek(&Token::Colon)?;
+            let value = self.allowing_struct_literals(true,
+                |parser| parser.parse_expression(BindingPower::Lowest))?;
+            fields.push((field, value));
+
+            if self.peek_token != Token::Comma { break; }
+            self.next_token()?;
+        }
+
+        self.expect_peek(&Token::RBrace)?;
+        let span = name.span.to(&self.span);
+
+        Ok(Expression::new(ExprKind::StructLit { name, fields }, span))
+    }
+
+    /// Parses `Enum::Variant` or `Enum::Variant(args)` once `Enum` has been read.
+    fn parse_variant_expression(&mut self, enum_name: String) -> Result<Expression> {
+        let enum_name = Spanned { node: enum_name, span: self.span.clone() };
+        self.next_token()?; // ::
+        let variant = self.expect_ident()?;
+        let variant = Spanned { node: variant, span: self.span.clone() };
+
+        let args = match self.peek_token {
+            Token::LParen => self.parse_call_arguments()?,
+            _ => vec![],
+        };
+        let span = enum_name.span.to(&self.span);
+
+        Ok(Expression::new(ExprKind::Variant { enum_name, variant, args }, span))
+    }
+
+    /// Parses `[a, b, ...]` once `[` has been read, allowing a trailing comma.
+    fn parse_array_literal(&mut self) -> Result<Expression> {
+        let start = self.span.clone();
+        let mut elements = vec![];
+
+        while self.peek_token != Token::RBracket {
+            elements.push(self.allowing_struct_literals(true,
+                |parser| parser.parse_expression(BindingPower::Lowest))?);
+            if self.peek_token != Token::Comma { break; }
+            self.next_token()?;
+        }
+
+        self.expect_peek(&Token::RBracket)?;
+
+        Ok(Expression::new(ExprKind::Array { elements }, start.to(&self.span)))
+    }
+
+    /// Parses `[index]` or `[start..end]` after `left`. Either bound of a
+    /// range may be left out.
+    fn parse_index_expression(&mut self, left: Expression) -> Result<Expression> {
+        let left_span = left.span.clone();
+        self.next_token()?; // [
+        let start = match self.peek_token {
+            Token::DotDot => None,
+            _ => Some(self.allowing_struct_literals(true,
+                |parser| parser.parse_expression(BindingPower::Lowest))?),
+        };
 
-                Expression {
-                    vtype: *decl.vtype.clone(),
-                    kind: ExprKind::Call {
-                        func: left.into(),
-                        args,
-                    },
+        let kind = match start {
+            Some(index) if self.peek_token != Token::DotDot => ExprKind::Index {
+                value: left.into(),
+                index: index.into(),
+            },
+            start => {
+                self.next_token()?; // ..
+                let end = match self.peek_token {
+                    Token::RBracket => None,
+                    _ => Some(self.allowing_struct_literals(true,
+                        |parser| parser.parse_expression(BindingPower::Lowest))?),
+                };
+
+                ExprKind::Slice {
+                    value: left.into(),
+                    start: start.map(Box::new),
+                    end: end.map(Box::new),
                 }
             }
-            _ => bail!("{:?} is not a function", left),
-        })
+        };
+
+        self.expect_peek(&Token::RBracket)?;
+
+        Ok(Expression::new(kind, left_span.to(&self.span)))
+    }
+
+    fn parse_field_expression(&mut self, left: Expression) -> Result<Expression> {
+        self.next_token()?; // .
+        let field = self.expect_ident()?;
+        let field = Spanned { node: field, span: self.span.clone() };
+        let span = left.span.to(&field.span);
+
+        Ok(Expression::new(ExprKind::Field {
+            value: left.into(),
+            field,
+        }, span))
+    }
+
+    fn parse_binary_expression(&mut self, left: Expression) -> Result<Expression> {
+        let op = self.next_token()?;
+        let right = self.parse_expression(Parser::get_binding_power(&op))?;
+        let span = left.span.to(&right.span);
+
+        Ok(Expression::new(ExprKind::Binary {
+            op,
+            left: left.into(),
+            right: right.into(),
+        }, span))
+    }
+
+    fn parse_assign_expression(&mut self, target: Expression) -> Result<Expression> {
+        let op = self.next_token()?.compound_op();
+        // Assignment groups to the right, so `a = b = c` assigns `b` first.
+        let value = self.parse_expression(BindingPower::Lowest)?;
+        let span = target.span.to(&value.span);
+
+        Ok(Expression::new(ExprKind::Assign {
+            op,
+            target: target.into(),
+            value: value.into(),
+        }, span))
+    }
+
+    fn parse_cast_expression(&mut self, left: Expression) -> Result<Expression> {
+        self.next_token()?; // as
+        let vtype = self.parse_type()?;
+        let span = left.span.to(&vtype.span);
+
+        Ok(Expression::new(ExprKind::Cast {
+            value: left.into(),
+            vtype,
+        }, span))
+    }
+
+    fn parse_call_expression(&mut self, left: Expression) -> Result<Expression> {
+        let args = self.parse_call_arguments()?;
+        let span = left.span.to(&self.span);
+
+        Ok(Expression::new(ExprKind::Call {
+            func: left.into(),
+            args,
+        }, span))
     }
 
-    fn parse_call_arguments(&mut self, env: &Environment) -> anyhow::Result<Vec<Expression>> {
+    fn parse_call_arguments(&mut self) -> Result<Vec<Expression>> {
         self.next_token()?; // (
         let mut args = vec![];
 
@@ -188,7 +470,8 @@ impl Parser {
         }
 
         loop {
-            args.push(self.parse_expression(BindingPower::Lowest, env)?);
+            args.push(self.allowing_struct_literals(true,
+                |parser| parser.parse_expression(BindingPower::Lowest))?);
             if self.peek_token != Token::Comma { break; }
             self.next_token()?;
         }
@@ -198,141 +481,246 @@ impl Parser {
         Ok(args)
     }
 
-    fn parse_statement(&mut self, env: &mut Environment) -> anyhow::Result<Statement> {
-        let res = match self.peek_token {
+    /// Parses a type name, `[T; N]` for arrays, `[]T` for slices or `*T`
+    /// for pointers.
+    fn parse_type(&mut self) -> Result<Spanned<ValueType>> {
+        if self.peek_token == Token::Asterisk {
+            self.next_token()?;
+            let start = self.span.clone();
+            let pointee = self.parse_type()?;
+            return Ok(Spanned { node: ValueType::Pointer(pointee.node.into()), span: start.to(&pointee.span) });
+        }
+
+        if self.peek_token != Token::LBracket {
+            let name = self.expect_ident()?;
+            return Ok(Spanned { node: ValueType::Type(name), span: self.span.clone() });
+        }
+
+        self.next_token()?; // [
+        let start = self.span.clone();
+        if self.peek_token == Token::RBracket {
+            self.next_token()?;
+            let element = self.parse_type()?;
+            return Ok(Spanned { node: ValueType::Slice(element.node.into()), span: start.to(&element.span) });
+        }
+
+        let element = self.parse_type()?;
+        self.expect_peek(&Token::Semicolon)?;
+
+        let len = match self.next_token()? {
+            Token::Int(lit) => match self.parse_int(&lit)?.kind {
+                ExprKind::Int { value: 0, suffix: None } => return Err(Diagnostic::error(self.span.clone(),
+                        "array length must be at least 1")
+                    .with_code("E0013")
+                    .with_primary("arrays cannot be empty")),
+                ExprKind::Int { value, suffix: None } => value,
+                _ => return Err(Diagnostic::error(self.span.clone(), "array length cannot have a suffix")
+                    .with_code("E0003")),
+            },
+            token => return Err(Diagnostic::error(self.span.clone(),
+                    format!("expected array length but got {}", token))
+                .with_code("E0001")
+                .with_primary("expected an integer literal")),
+        };
+        self.expect_peek(&Token::RBracket)?;
+
+        Ok(Spanned { node: ValueType::Array(element.node.into(), len), span: start.to(&self.span) })
+    }
+
+    fn parse_statement(&mut self) -> Result<Statement> {
+        let start = self.peek_span.clone();
+
+        let kind = match self.peek_token {
             Token::Let => {
                 self.next_token()?; // let
+                let mutable = self.parse_mutability()?;
                 let name = self.expect_ident()?;
+                let name = Spanned { node: name, span: self.span.clone() };
 
-                if env.get_vtype_of(&name).is_some() {
-                    bail!("{} already exists", name);
-                }
-
-                self.expect_peek(&Token::Colon)?;
-                let vtype = self.expect_ident()?;
-
-                if !env.does_vtype_exist(&vtype) {
-                    bail!("{} not found in current scope", vtype);
-                }
+                // Without an annotation the type checker infers it.
+                let vtype = match self.peek_token {
+                    Token::Colon => {
+                        self.next_token()?; // :
+                        self.parse_type()?
+                    }
+                    _ => Spanned { node: ValueType::Unknown, span: name.span.clone() },
+                };
 
                 let value = match self.peek_token {
                     Token::Assign => {
                         self.next_token()?; // =
-                        let value = self.parse_expression(BindingPower::Lowest, env)?;
-
-                        if ValueType::Type(vtype.clone()) != value.vtype {
-                            bail!("{:?} is not of type {}", value, vtype);
-                        }
-
-                        Some(value)
+                        Some(self.parse_expression(BindingPower::Lowest)?)
                     }
                     _ => None,
                 };
 
-                env.push_symbol(Symbol { 
-                    name: name.to_owned(), 
-                    vtype: ValueType::Type(vtype.to_owned()) 
-                })?;
-
-                Statement::Let { name, vtype, value }
+                StmtKind::Let { name, mutable, vtype, value }
             }
             Token::Return => {
                 self.next_token()?; // return
-                let value = self.parse_expression(BindingPower::Lowest, env)?;
-                Statement::Return { value }
+                let value = self.parse_expression(BindingPower::Lowest)?;
+                StmtKind::Return { value }
             }
             Token::If => {
                 self.next_token()?; // if
-                let cond = self.parse_expression(BindingPower::Lowest, env)?;
-                let then = self.parse_block_statement(env)?;
+                let cond = self.parse_header_expression()?;
+                let then = self.parse_block_statement()?;
 
                 let else_then = if self.peek_token == Token::Else {
-                    self.next_token()?;
-                    Some(self.parse_block_statement(env)?)
+                    self.next_token()?; // else
+
+                    if self.peek_token == Token::If {
+                        Some(self.parse_statement()?.into())
+                    } else {
+                        let else_start = self.peek_span.clone();
+                        let body = self.parse_block_statement()?;
+
+                        Some(Statement {
+                            kind: StmtKind::Block { body },
+                            span: else_start.to(&self.span),
+                        }.into())
+                    }
                 } else {
                     None
                 };
 
-                return Ok(Statement::If { cond, then, else_then });
+                return Ok(Statement {
+                    kind: StmtKind::If { cond, then, else_then },
+                    span: start.to(&self.span),
+                });
+            }
+            Token::While => {
+                self.next_token()?; // while
+                let cond = self.parse_header_expression()?;
+                let body = self.parse_block_statement()?;
+
+                return Ok(Statement {
+                    kind: StmtKind::While { cond, body },
+                    span: start.to(&self.span),
+                });
+            }
+            Token::For => {
+                self.next_token()?; // for
+                let var = self.expect_ident()?;
+                let var = Spanned { node: var, span: self.span.clone() };
+
+                self.expect_peek(&Token::In)?;
+                let from = self.parse_header_expression()?;
+                self.expect_peek(&Token::DotDot)?;
+                let to = self.parse_header_expression()?;
+                let body = self.parse_block_statement()?;
+
+                return Ok(Statement {
+                    kind: StmtKind::For { var, start: from, end: to, body },
+                    span: start.to(&self.span),
+                });
+            }
+            Token::Break => {
+                self.next_token()?; // break
+                StmtKind::Break
+            }
+            Token::Continue => {
+                self.next_token()?; // continue
+                StmtKind::Continue
+            }
+            Token::LBrace => {
+                let body = self.parse_block_statement()?;
+
+                return Ok(Statement {
+                    kind: StmtKind::Block { body },
+                    span: start.to(&self.span),
+                });
             }
-            Token::LBrace => return Ok(self.parse_block_statement(env)?.into()),
             Token::Fn => {
                 self.next_token()?; // fn
                 let name = self.expect_ident()?;
-
-                if env.get_vtype_of(&name).is_some() {
-                    bail!("{} already exists", name);
-                }
-
-                let params = self.parse_func_params(env)?;
+                let params = self.parse_func_params()?;
 
                 self.expect_peek(&Token::Arrow)?;
-                let vtype = self.expect_ident()?;
-
-                if !env.does_vtype_exist(&vtype) {
-                    bail!("{} not found in current scope", vtype);
-                }
+                let vtype = self.parse_type()?;
 
                 let decl = FuncDecl {
-                    name: name.clone(),
-                    vtype: ValueType::Type(vtype).into(),
-                    params: params.clone(),
+                    name,
+                    vtype: vtype.node.into(),
+                    params,
+                    span: start.to(&self.span),
                 };
 
-                env.push_symbol(Symbol { 
-                    name, 
-                    vtype: ValueType::Func(decl.clone()),
-                })?;
+                let body = self.parse_block_statement()?;
 
-                let mut env = Environment::from_parent(env);
-
-                for param in &params {
-                    env.push_symbol(param.clone())?;
-                }
+                return Ok(Statement {
+                    kind: StmtKind::Func { decl, body },
+                    span: start.to(&self.span),
+                });
+            }
+            Token::Struct => {
+                self.next_token()?; // struct
+                let name = self.expect_ident()?;
+                let span = start.to(&self.span);
+                let fields = self.parse_struct_fields()?;
 
-                let body = self.parse_block_statement(&env)?;
+                return Ok(Statement {
+                    kind: StmtKind::Struct { decl: StructDecl { name, fields, span } },
+                    span: start.to(&self.span),
+                });
+            }
+            Token::Enum => {
+                self.next_token()?; // enum
+                let name = self.expect_ident()?;
+                let span = start.to(&self.span);
+                let variants = self.parse_enum_variants()?;
 
-                return Ok(Statement::Func {
-                    decl,
-                    body,
+                return Ok(Statement {
+                    kind: StmtKind::Enum { decl: EnumDecl { name, variants, span } },
+                    span: start.to(&self.span),
                 });
             }
-            _ => Statement::Expression {
-                value: self.parse_expression(BindingPower::Lowest, env)?
+            Token::Match => {
+                self.next_token()?; // match
+                let value = self.parse_header_expression()?;
+                let arms = self.parse_match_arms()?;
+
+                return Ok(Statement {
+                    kind: StmtKind::Match { value, arms },
+                    span: start.to(&self.span),
+                });
+            }
+            _ => StmtKind::Expression {
+                value: self.parse_expression(BindingPower::Lowest)?
             },
         };
 
         self.expect_peek(&Token::Semicolon)?;
 
-        Ok(res)
+        Ok(Statement { kind, span: start.to(&self.span) })
     }
 
-    fn parse_block_statement(&mut self, env: &Environment) -> anyhow::Result<BlockStmt> {
+    /// Parses a braced block. Errors inside the block are recorded in
+    /// `self.diagnostics` so the statements around them are still parsed.
+    fn parse_block_statement(&mut self) -> Result<BlockStmt> {
         self.expect_peek(&Token::LBrace)?;
         let mut body = vec![];
-        let mut errs = vec![];
-        let mut env = Environment::from_parent(env);
 
-        while ![Token::Eof, Token::RBrace].contains(&self.peek_token) {
-            match self.parse_statement(&mut env) {
+        while ![Token::Eof, Token::RBrace].contains(&self.peek_token)
+                && !self.error_limit_reached() {
+            match self.parse_statement() {
                 Ok(stmt) => body.push(stmt),
-                Err(err) => errs.push(err),
+                Err(diag) => {
+                    if !self.report(diag) { break; }
+                    self.synchronize();
+                }
             }
         }
 
-        if let Err(err) = self.expect_peek(&Token::RBrace) {
-            errs.push(err);
+        if !self.error_limit_reached()
+                && let Err(diag) = self.expect_peek(&Token::RBrace) {
+            self.report(diag);
         }
 
-        if errs.is_empty() {
-            Ok(BlockStmt(body))
-        } else {
-            bail!(errs.iter().map(|err| format!("{err}"))
-                .reduce(|acc, err| format!("{acc}\n{err}")).unwrap_or_default());
-        }
+        Ok(BlockStmt(body))
     }
 
-    fn parse_func_params(&mut self, env: &Environment) -> anyhow::Result<Vec<Symbol>> {
+    fn parse_func_params(&mut self) -> Result<Vec<Symbol>> {
         self.expect_peek(&Token::LParen)?;
         let mut params = vec![];
 
@@ -342,7 +730,7 @@ impl Parser {
         }
 
         loop {
-            params.push(self.parse_func_param(env)?);
+            params.push(self.parse_func_param()?);
             if self.peek_token != Token::Comma { break; }
             self.next_token()?;
         }
@@ -352,44 +740,151 @@ impl Parser {
         Ok(params)
     }
 
-    fn parse_func_param(&mut self, env: &Environment) -> anyhow::Result<Symbol> {
-        let name = self.expect_ident()?;
+    /// Parses `{ Name, Name(type, ...), ... }`, allowing a trailing comma.
+    fn parse_enum_variants(&mut self) -> Result<Vec<Variant>> {
+        self.expect_peek(&Token::LBrace)?;
+        let mut variants = vec![];
+
+        while self.peek_token != Token::RBrace {
+            let name = self.expect_ident()?;
+            let span = self.span.clone();
+            let mut fields = vec![];
+
+            if self.peek_token == Token::LParen {
+                self.next_token()?; // (
+                while self.peek_token != Token::RParen {
+                    fields.push(self.parse_type()?.node);
+                    if self.peek_token != Token::Comma { break; }
+                    self.next_token()?;
+                }
+                self.expect_peek(&Token::RParen)?;
+            }
 
-        self.expect_peek(&Token::Colon)?;
-        let vtype = self.expect_ident()?;
+            variants.push(Variant { name, fields, span: span.to(&self.span) });
+
+            if self.peek_token != Token::Comma { break; }
+            self.next_token()?;
+        }
+
+        self.expect_peek(&Token::RBrace)?;
 
-        if !env.does_vtype_exist(&vtype) {
-            bail!("{} not found in current scope", vtype);
+        Ok(variants)
+    }
+
+    /// Parses `{ pattern => { ... }, ... }`. Commas between arms are optional.
+    fn parse_match_arms(&mut self) -> Result<Vec<MatchArm>> {
+        self.expect_peek(&Token::LBrace)?;
+        let mut arms = vec![];
+
+        while self.peek_token != Token::RBrace {
+            let pattern = self.parse_pattern()?;
+            self.expect_peek(&Token::FatArrow)?;
+            let body = self.parse_block_statement()?;
+            arms.push(MatchArm { pattern, body });
+
+            if self.peek_token == Token::Comma {
+                self.next_token()?;
+            }
+        }
+
+        self.expect_peek(&Token::RBrace)?;
+
+        Ok(arms)
+    }
+
+    /// Parses `_`, `Enum::Variant` or `Enum::Variant(a, b, ...)`.
+    fn parse_pattern(&mut self) -> Result<Spanned<Pattern>> {
+        let enum_name = self.expect_ident()?;
+        let start = self.span.clone();
+
+        if enum_name == "_" {
+            return Ok(Spanned { node: Pattern::Wildcard, span: start });
         }
 
-        Ok(Symbol { name, vtype: ValueType::Type(vtype) })
+        let enum_name = Spanned { node: enum_name, span: start.clone() };
+        self.expect_peek(&Token::ColonColon)?;
+        let variant = self.expect_ident()?;
+        let variant = Spanned { node: variant, span: self.span.clone() };
+        let mut bindings = vec![];
+
+        if self.peek_token == Token::LParen {
+            self.next_token()?; // (
+            while self.peek_token != Token::RParen {
+                let name = self.expect_ident()?;
+                bindings.push(Symbol { name, vtype: ValueType::Unknown, span: self.span.clone(), mutable: false });
+                if self.peek_token != Token::Comma { break; }
+                self.next_token()?;
+            }
+            self.expect_peek(&Token::RParen)?;
+        }
+
+        Ok(Spanned {
+            node: Pattern::Variant { enum_name, variant, bindings },
+            span: start.to(&self.span),
+        })
     }
 
-    pub fn parse_program(&mut self) -> anyhow::Result<Program> {
+    /// Parses `{ name: type, ... }`, allowing a trailing comma.
+    fn parse_struct_fields(&mut self) -> Result<Vec<Symbol>> {
+        self.expect_peek(&Token::LBrace)?;
+        let mut fields = vec![];
+
+        while self.peek_token != Token::RBrace {
+            let name = self.expect_ident()?;
+            let span = self.span.clone();
+            self.expect_peek(&Token::Colon)?;
+            let vtype = self.parse_type()?;
+            fields.push(Symbol { name, vtype: vtype.node, span, mutable: false });
+
+            if self.peek_token != Token::Comma { break; }
+            self.next_token()?;
+        }
+
+        self.expect_peek(&Token::RBrace)?;
+
+        Ok(fields)
+    }
+
+    /// Consumes a `mut` if one comes next.
+    fn parse_mutability(&mut self) -> Result<bool> {
+        if self.peek_token != Token::Mut {
+            return Ok(false);
+        }
+
+        self.next_token()?;
+        Ok(true)
+    }
+
+    fn parse_func_param(&mut self) -> Result<Symbol> {
+        let mutable = self.parse_mutability()?;
+        let name = self.expect_ident()?;
+        let span = self.span.clone();
+
+        self.expect_peek(&Token::Colon)?;
+        let vtype = self.parse_type()?;
+
+        Ok(Symbol { name, vtype: vtype.node, span, mutable })
+    }
+
+    /// Parses the whole input. On failure every diagnostic collected along the
+    /// way is returned, warnings included.
+    pub fn parse_program(&mut self) -> std::result::Result<Program, Vec<Diagnostic>> {
         let mut body = vec![];
-        let mut errs = vec![];
-        let mut env = Environment::new();
-
-        env.push_vtype(ValueType::Type("i32".to_owned())).unwrap();
-        env.push_vtype(ValueType::Type("str".to_owned())).unwrap();
-        env.push_symbol(Symbol { name: "printf".to_owned(), vtype: ValueType::Func(FuncDecl { 
-            name: "printf".to_owned(), 
-            vtype: ValueType::Type("void".to_owned()).into(), 
-            params: vec![Symbol {
-                name: "str".to_owned(),
-                vtype: ValueType::str(),
-            }],
-        }) }).unwrap();
-
-        while self.peek_token != Token::Eof {
-            match self.parse_statement(&mut env) {
+
+        while self.peek_token != Token::Eof && !self.error_limit_reached() {
+            match self.parse_statement() {
                 Ok(stmt) => body.push(stmt),
-                Err(err) => errs.push(err),
+                Err(diag) => {
+                    if !self.report(diag) { break; }
+                    self.synchronize();
+                }
             }
         }
 
-        if !errs.is_empty() { bail!(errs.iter().map(|err| format!("{err}"))
-            .reduce(|acc, err| format!("{acc}\n{err}")).unwrap_or_default()) }
+        if self.diagnostics.iter().any(Diagnostic::is_error) {
+            return Err(std::mem::take(&mut self.diagnostics));
+        }
+
         Ok(Program { body })
     }
 }
@@ -405,14 +900,14 @@ pub fn repl() {
         let mut buffer = String::new();
         std::io::stdin().read_line(&mut buffer).unwrap();
 
-        let lexer = Lexer::new(buffer.into_bytes());
+        let input = buffer.into_bytes();
+        let emitter = Emitter::new(&input, std::io::stderr().is_terminal());
+        let lexer = Lexer::new(input.clone());
         let mut parser = Parser::new(lexer).unwrap();
 
-        let mut env = Environment::new();
-
-        match parser.parse_statement(&mut env) {
+        match parser.parse_statement() {
             Ok(expr) => println!("{:?}", expr),
-            Err(err) => eprintln!("{err}"),
+            Err(diag) => eprint!("{}", emitter.render(&diag)),
         };
     }
 }
@@ -439,4 +934,231 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_error_location() -> anyhow::Result<()> {
+        let input = b"fn main() -> i32 {
+    return 1
+}";
+        let lexer = Lexer::from_file("test.sxl", input.to_vec());
+        let mut parser = Parser::new(lexer)?;
+
+        let errs = parser.parse_program().err().unwrap();
+        assert_eq!(errs.len(), 1);
+        assert_eq!(format!("{}", errs[0]), "test.sxl:3:1: expected ; but got }");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_recovery() -> anyhow::Result<()> {
+        let input = b"fn main() -> i32 {
+    let a: i32 = 1 +;
+    if a == 1 {
+        printf(a;
+    }
+    let b: i32 = a
+    return b;
+}
+
+fn other() -> i32 {
+    return );
+}";
+        let lexer = Lexer::from_file("test.sxl", input.to_vec());
+        let mut parser = Parser::new(lexer)?;
+
+        let errs = parser.parse_program().err().unwrap();
+        let lines: Vec<_> = errs.iter().map(|err| err.primary.span.line).collect();
+        assert_eq!(lines, vec![2, 4, 7, 11]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_limit() -> anyhow::Result<()> {
+        let input = "let a: i32 = ;\n".repeat(MAX_ERRORS * 2);
+        let lexer = Lexer::new(input.into_bytes());
+        let mut parser = Parser::new(lexer)?;
+
+        let errs = parser.parse_program().err().unwrap();
+        assert_eq!(errs.len(), MAX_ERRORS);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_int_literals() -> anyhow::Result<()> {
+        let cases = [
+            ("42", 42, None),
+            ("0xff", 255, None),
+            ("0o17", 15, None),
+            ("0b1010_1010", 170, None),
+            ("10u8", 10, Some("u8")),
+            ("5_000_000_000i64", 5_000_000_000, Some("i64")),
+            ("0xffu16", 255, Some("u16")),
+            ("18446744073709551615", u64::MAX, None),
+        ];
+
+        for (lit, value, suffix) in cases {
+            let mut parser = Parser::new(Lexer::new(lit.as_bytes().to_vec()))?;
+            let expr = parser.parse_expression(BindingPower::Lowest)?;
+            assert_eq!(expr.kind, ExprKind::Int {
+                value,
+                suffix: suffix.map(|suffix| ValueType::Type(suffix.to_owned())),
+            });
+        }
+
+        for lit in ["18446744073709551616", "10u7", "0x", "12abc", "0b102"] {
+            let mut parser = Parser::new(Lexer::new(lit.as_bytes().to_vec()))?;
+            assert!(parser.parse_expression(BindingPower::Lowest).is_err(), "{lit}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_grouping() -> anyhow::Result<()> {
+        let mut parser = Parser::new(Lexer::new(b"(a + b) * c".to_vec()))?;
+        let expr = parser.parse_expression(BindingPower::Lowest)?;
+
+        let ExprKind::Binary { op: Token::Asterisk, left, .. } = expr.kind else {
+            panic!("expected a product, got {:?}", expr.kind);
+        };
+        assert!(matches!(left.kind, ExprKind::Binary { op: Token::Plus, .. }));
+        assert_eq!((left.span.start, left.span.end), (0, 7));
+
+        let mut parser = Parser::new(Lexer::new(b"(a + b * c".to_vec()))?;
+        assert!(parser.parse_expression(BindingPower::Lowest).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_struct_literals() -> anyhow::Result<()> {
+        let lexer = Lexer::new(b"if p == Point { x: 1 }.x { } while f(P { a: 1 }) { }".to_vec());
+        let mut parser = Parser::new(lexer)?;
+        let StmtKind::If { cond, .. } = parser.parse_statement()?.kind else { panic!() };
+        assert!(matches!(cond.kind, ExprKind::Binary { .. }));
+
+        // The literal's braces were taken as the body of the `if`.
+        assert!(parser.parse_statement().is_err());
+
+        let lexer = Lexer::new(b"while f(P { a: 1 }).b { }".to_vec());
+        let StmtKind::While { cond, .. } = Parser::new(lexer)?.parse_statement()?.kind else { panic!() };
+        let ExprKind::Field { value, field } = cond.kind else { panic!() };
+        assert_eq!(field.node, "b");
+        let ExprKind::Call { args, .. } = value.kind else { panic!() };
+        assert!(matches!(args[0].kind, ExprKind::StructLit { .. }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cast_precedence() -> anyhow::Result<()> {
+        let mut parser = Parser::new(Lexer::new(b"-a as u8 * b".to_vec()))?;
+        let expr = parser.parse_expression(BindingPower::Lowest)?;
+
+        let ExprKind::Binary { op: Token::Asterisk, left, .. } = expr.kind else {
+            panic!("expected a product, got {:?}", expr.kind);
+        };
+        let ExprKind::Cast { value, vtype } = left.kind else {
+            panic!("expected a cast, got {:?}", left.kind);
+        };
+        assert!(matches!(value.kind, ExprKind::Unary { op: Token::Minus, .. }));
+        assert_eq!(vtype.node, ValueType::Type("u8".to_owned()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_arrays() -> anyhow::Result<()> {
+        let lexer = Lexer::new(b"let a: [[u8; 2]; 3] = [[1, 2], [3, 4], [5, 6],];".to_vec());
+        let StmtKind::Let { vtype, value, .. } = Parser::new(lexer)?.parse_statement()?.kind else { panic!() };
+        assert_eq!(vtype.node.to_string(), "[[u8; 2]; 3]");
+        let Some(ExprKind::Array { elements }) = value.map(|value| value.kind) else { panic!() };
+        assert_eq!(elements.len(), 3);
+
+        // Indexing binds like a call, tighter than unary operators.
+        let mut parser = Parser::new(Lexer::new(b"-a.b[i + 1][0]".to_vec()))?;
+        let expr = parser.parse_expression(BindingPower::Lowest)?;
+        let ExprKind::Unary { right, .. } = expr.kind else { panic!() };
+        let ExprKind::Index { value, .. } = right.kind else { panic!() };
+        let ExprKind::Index { value, index } = value.kind else { panic!() };
+        assert!(matches!(value.kind, ExprKind::Field { .. }));
+        assert!(matches!(index.kind, ExprKind::Binary { .. }));
+
+        for input in ["let a: [i32; 0];", "let a: [i32; n];", "let a: [i32; 3u8];", "let a: [i32 3];"] {
+            let mut parser = Parser::new(Lexer::new(input.as_bytes().to_vec()))?;
+            assert!(parser.parse_statement().is_err(), "{input}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_slices() -> anyhow::Result<()> {
+        let lexer = Lexer::new(b"fn f(xs: [][u8; 4]) -> []i32 { }".to_vec());
+        let StmtKind::Func { decl, .. } = Parser::new(lexer)?.parse_statement()?.kind else { panic!() };
+        assert_eq!(decl.params[0].vtype.to_string(), "[][u8; 4]");
+        assert_eq!(decl.vtype.to_string(), "[]i32");
+
+        let cases = [("a[..]", false, false), ("a[i..]", true, false), ("a[..n - 1]", false, true), ("a[1..n]", true, true)];
+        for (input, has_start, has_end) in cases {
+            let mut parser = Parser::new(Lexer::new(input.as_bytes().to_vec()))?;
+            let expr = parser.parse_expression(BindingPower::Lowest)?;
+            let ExprKind::Slice { start, end, .. } = expr.kind else { panic!("{input}: {:?}", expr.kind) };
+            assert_eq!((start.is_some(), end.is_some()), (has_start, has_end), "{input}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pointers() -> anyhow::Result<()> {
+        let lexer = Lexer::new(b"fn f(p: **i32, xs: *[]u8) -> *[*i32; 2] { }".to_vec());
+        let StmtKind::Func { decl, .. } = Parser::new(lexer)?.parse_statement()?.kind else { panic!() };
+        assert_eq!(decl.params[0].vtype.to_string(), "**i32");
+        assert_eq!(decl.params[1].vtype.to_string(), "*[]u8");
+        assert_eq!(decl.vtype.to_string(), "*[*i32; 2]");
+
+        // Prefix `*` and `&` bind looser than fields and tighter than binary operators.
+        let mut parser = Parser::new(Lexer::new(b"*p.x * &a[0] & b".to_vec()))?;
+        let expr = parser.parse_expression(BindingPower::Lowest)?;
+        let ExprKind::Binary { op: Token::Ampersand, left, .. } = expr.kind else { panic!("{:?}", expr.kind) };
+        let ExprKind::Binary { op: Token::Asterisk, left, right } = left.kind else { panic!("{:?}", left.kind) };
+        let ExprKind::Unary { op: Token::Asterisk, right: deref } = left.kind else { panic!("{:?}", left.kind) };
+        assert!(matches!(deref.kind, ExprKind::Field { .. }));
+        let ExprKind::Unary { op: Token::Ampersand, right: place } = right.kind else { panic!("{:?}", right.kind) };
+        assert!(matches!(place.kind, ExprKind::Index { .. }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_float_literals() -> anyhow::Result<()> {
+        let cases = [
+            ("2.75", 2.75, None),
+            ("1e3", 1000.0, None),
+            ("2.5E-2", 0.025, None),
+            ("1_000.5", 1000.5, None),
+            ("0.5f32", 0.5, Some("f32")),
+            ("2f64", 2.0, Some("f64")),
+        ];
+
+        for (lit, value, suffix) in cases {
+            let mut parser = Parser::new(Lexer::new(lit.as_bytes().to_vec()))?;
+            let expr = parser.parse_expression(BindingPower::Lowest)?;
+            assert_eq!(expr.kind, ExprKind::Float {
+                value,
+                suffix: suffix.map(|suffix| ValueType::Type(suffix.to_owned())),
+            });
+        }
+
+        for lit in ["1.5u8", "1.0f16", "1e400", "1.5ex"] {
+            let mut parser = Parser::new(Lexer::new(lit.as_bytes().to_vec()))?;
+            assert!(parser.parse_expression(BindingPower::Lowest).is_err(), "{lit}");
+        }
+
+        Ok(())
+    }
 }
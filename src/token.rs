@@ -1,3 +1,46 @@
+use std::sync::Arc;
+
+/// A region of source code, used to point diagnostics at the offending text.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Span {
+    pub file: Arc<str>,
+    pub line: usize,
+    pub col: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// Returns a span covering both `self` and `other`.
+    pub fn to(&self, other: &Span) -> Span {
+        let (first, last) = if self.start <= other.start {
+            (self, other)
+        } else {
+            (other, self)
+        };
+
+        Span {
+            file: first.file.clone(),
+            line: first.line,
+            col: first.col,
+            start: first.start,
+            end: first.end.max(last.end),
+        }
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.col)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Illegal,
@@ -5,28 +48,54 @@ pub enum Token {
 
     Ident(String),
     Int(String),
+    Float(String),
     String(String),
 
     Assign,
+    PlusAssign,
+    MinusAssign,
+    AsteriskAssign,
+    SlashAssign,
+    PercentAssign,
+    AmpersandAssign,
+    PipeAssign,
+    CaretAssign,
+    ShlAssign,
+    ShrAssign,
     Plus,
     Minus,
     Asterisk,
     Slash,
+    Percent,
     Bang,
+    Tilde,
+    Ampersand,
+    Pipe,
+    Caret,
+    Shl,
+    Shr,
     Equal,
     NotEqual,
     Lt,
     Gt,
     Lte,
     Gte,
+    And,
+    Or,
 
     Comma,
     Colon,
+    ColonColon,
+    FatArrow,
     Semicolon,
     Arrow,
+    Dot,
+    DotDot,
 
     LParen,
     RParen,
+    LBracket,
+    RBracket,
     LBrace,
     RBrace,
 
@@ -35,6 +104,16 @@ pub enum Token {
     If,
     Else,
     Return,
+    While,
+    For,
+    In,
+    As,
+    Mut,
+    Struct,
+    Enum,
+    Match,
+    Break,
+    Continue,
     True,
     False,
 }
@@ -48,11 +127,44 @@ impl Token {
             "if" => If,
             "else" => Else,
             "return" => Return,
+            "while" => While,
+            "for" => For,
+            "in" => In,
+            "as" => As,
+            "mut" => Mut,
+            "struct" => Struct,
+            "enum" => Enum,
+            "match" => Match,
+            "break" => Break,
+            "continue" => Continue,
             "true" => True,
             "false" => False,
             _ => Ident(symbol.to_string()),
         }
     }
+
+    /// Returns the operator a compound assignment like `+=` applies.
+    pub fn compound_op(&self) -> Option<Token> {
+        use Token::*;
+        Some(match self {
+            PlusAssign => Plus,
+            MinusAssign => Minus,
+            AsteriskAssign => Asterisk,
+            SlashAssign => Slash,
+            PercentAssign => Percent,
+            AmpersandAssign => Ampersand,
+            PipeAssign => Pipe,
+            CaretAssign => Caret,
+            ShlAssign => Shl,
+            ShrAssign => Shr,
+            _ => return None,
+        })
+    }
+
+    /// Whether this is `=` or one of the compound assignments.
+    pub fn is_assign(&self) -> bool {
+        *self == Token::Assign || self.compound_op().is_some()
+    }
 }
 
 impl std::fmt::Display for Token {
@@ -65,28 +177,54 @@ impl std::fmt::Display for Token {
 
             Ident(name) => name,
             Int(lit) => lit,
-            String(lit) => &format!("\"{lit}\""),
+            Float(lit) => lit,
+            String(lit) => &format!("{lit:?}"),
 
             Assign => "=",
+            PlusAssign => "+=",
+            MinusAssign => "-=",
+            AsteriskAssign => "*=",
+            SlashAssign => "/=",
+            PercentAssign => "%=",
+            AmpersandAssign => "&=",
+            PipeAssign => "|=",
+            CaretAssign => "^=",
+            ShlAssign => "<<=",
+            ShrAssign => ">>=",
             Plus => "+",
             Minus => "-",
             Asterisk => "*",
             Slash => "/",
+            Percent => "%",
             Bang => "!",
+            Tilde => "~",
+            Ampersand => "&",
+            Pipe => "|",
+            Caret => "^",
+            Shl => "<<",
+            Shr => ">>",
             Equal => "==",
             NotEqual => "!=",
             Lt => "<",
             Gt => ">",
             Lte => "<=",
             Gte => ">=",
+            And => "&&",
+            Or => "||",
 
             Comma => ",",
             Colon => ":",
+            ColonColon => "::",
+            FatArrow => "=>",
             Semicolon => ";",
             Arrow => "->",
+            Dot => ".",
+            DotDot => "..",
 
             LParen => "(",
             RParen => ")",
+            LBracket => "[",
+            RBracket => "]",
             LBrace => "{",
             RBrace => "}",
 
@@ -95,6 +233,16 @@ impl std::fmt::Display for Token {
             If => "if",
             Else => "else",
             Return => "return",
+            While => "while",
+            For => "for",
+            In => "in",
+            As => "as",
+            Mut => "mut",
+            Struct => "struct",
+            Enum => "enum",
+            Match => "match",
+            Break => "break",
+            Continue => "continue",
             True => "true",
             False => "false",
         };
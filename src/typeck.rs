@@ -0,0 +1,1936 @@
+use crate::{ast::{BlockStmt, EnumDecl, ExprKind, Expression, FuncDecl, MatchArm, Pattern, Program, Statement, StmtKind, StructDecl, Symbol, ValueType}, diagnostic::Diagnostic, environment::Environment, token::{Span, Token}};
+
+/// How control leaves a sequence of statements.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Flow {
+    /// Falls through to whatever follows.
+    Continues,
+    /// Leaves the function on every path.
+    Returns,
+    /// Leaves on every path, but through `break` or `continue` on some.
+    Jumps,
+}
+
+/// Walks a parsed `Program`, resolving names and filling in `Expression::vtype`.
+/// Expressions that fail to check are given `ValueType::Unknown`, which is
+/// accepted everywhere so that one mistake is only reported once.
+pub struct TypeChecker {
+    diagnostics: Vec<Diagnostic>,
+    return_vtype: Option<ValueType>,
+    loop_depth: usize,
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        Self {
+            diagnostics: vec![],
+            return_vtype: None,
+            loop_depth: 0,
+        }
+    }
+
+    fn report(&mut self, diag: Diagnostic) {
+        self.diagnostics.push(diag);
+    }
+
+    /// Reports a mismatch unless either side is already known to be broken.
+    fn expect_vtype(&mut self, expected: &ValueType, expr: &Expression, reason: Option<(&Span, &str)>) {
+        if *expected == ValueType::Unknown || expr.vtype == ValueType::Unknown
+                || *expected == expr.vtype {
+            return;
+        }
+
+        let mut diag = Diagnostic::error(expr.span.clone(), "mismatched types")
+            .with_code("E0006")
+            .with_primary(format!("expected {}, found {}", expected, expr.vtype));
+
+        if let Some((span, message)) = reason {
+            diag = diag.with_label(span.clone(), message);
+        }
+
+        self.report(diag);
+    }
+
+    /// Checks that a condition of an `if` or loop is a `bool`.
+    fn expect_condition(&mut self, cond: &Expression) {
+        if cond.vtype != ValueType::Unknown && cond.vtype != ValueType::bool() {
+            self.report(Diagnostic::error(cond.span.clone(), "mismatched types")
+                .with_code("E0006")
+                .with_primary(format!("expected bool, found {}", cond.vtype))
+                .with_note("conditions must be of type bool"));
+        }
+    }
+
+    /// Returns the type of `left op right`, or `None` if `op` doesn't apply.
+    /// Both sides must have the same type; there are no implicit conversions.
+    /// Shifts are the exception: the amount may be any integer type.
+    fn binary_vtype(op: &Token, left: &ValueType, right: &ValueType) -> Option<ValueType> {
+        if let Token::Shl | Token::Shr = op {
+            return (left.is_integer() && right.is_integer()).then(|| left.clone());
+        }
+
+        if left != right {
+            return None;
+        }
+
+        let is_str = *left == ValueType::str();
+        match op {
+            Token::Plus | Token::Minus | Token::Asterisk | Token::Slash
+                if left.is_numeric() => Some(left.clone()),
+            Token::Plus if is_str => Some(left.clone()),
+            Token::Percent | Token::Ampersand | Token::Pipe | Token::Caret
+                if left.is_integer() => Some(left.clone()),
+            Token::Lt | Token::Gt | Token::Lte | Token::Gte
+                if left.is_numeric() || is_str => Some(ValueType::bool()),
+            Token::Equal | Token::NotEqual
+                if left.is_numeric() || *left == ValueType::bool() || is_str => Some(ValueType::bool()),
+            Token::And | Token::Or if *left == ValueType::bool() => Some(ValueType::bool()),
+            _ => None,
+        }
+    }
+
+    fn unary_vtype(op: &Token, right: &ValueType) -> Option<ValueType> {
+        match op {
+            Token::Minus if right.is_signed() || right.is_float() => Some(right.clone()),
+            Token::Bang if *right == ValueType::bool() => Some(ValueType::bool()),
+            Token::Tilde if right.is_integer() => Some(right.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether `value as to` is allowed for a value of type `from`: any
+    /// numeric type to any other, and `bool` to an integer.
+    fn can_cast(from: &ValueType, to: &ValueType) -> bool {
+        from == to
+            || from.is_numeric() && to.is_numeric()
+            || *from == ValueType::bool() && to.is_integer()
+    }
+
+    fn check_vtype_exists(&mut self, vtype: &ValueType, span: &Span, env: &Environment) -> ValueType {
+        match vtype {
+            ValueType::Type(name) if !env.does_vtype_exist(name) => {
+                self.report(Diagnostic::error(span.clone(),
+                        format!("type {} not found in current scope", name))
+                    .with_code("E0004")
+                    .with_primary("unknown type"));
+                ValueType::Unknown
+            }
+            ValueType::Array(element, len) => match self.check_vtype_exists(element, span, env) {
+                ValueType::Unknown => ValueType::Unknown,
+                element => ValueType::Array(element.into(), *len),
+            },
+            ValueType::Slice(element) => match self.check_vtype_exists(element, span, env) {
+                ValueType::Unknown => ValueType::Unknown,
+                element => ValueType::Slice(element.into()),
+            },
+            ValueType::Pointer(pointee) => match self.check_vtype_exists(pointee, span, env) {
+                ValueType::Unknown => ValueType::Unknown,
+                pointee => ValueType::Pointer(pointee.into()),
+            },
+            vtype => vtype.clone(),
+        }
+    }
+
+    /// Introduces `symbol`, reporting it if the name is already taken in this
+    /// or any enclosing scope.
+    fn define(&mut self, symbol: Symbol, env: &mut Environment) {
+        if let Some(prev) = env.get_symbol(&symbol.name) {
+            let diag = Environment::redefinition(&symbol.name, &symbol.span, &prev.span);
+            self.report(diag);
+            return;
+        }
+
+        if let Err(diag) = env.push_symbol(symbol) {
+            self.report(diag);
+        }
+    }
+
+    fn check_expression(&mut self, expr: &mut Expression, env: &Environment) {
+        self.check_expression_as(expr, env, None);
+    }
+
+    /// Picks the type of an integer literal: its suffix if it has one, else
+    /// the integer type the context expects, else `i32`.
+    fn int_literal_vtype(suffix: Option<&ValueType>, hint: Option<&ValueType>) -> ValueType {
+        match (suffix, hint) {
+            (Some(suffix), _) => suffix.clone(),
+            (None, Some(hint)) if hint.is_integer() => hint.clone(),
+            _ => ValueType::i32(),
+        }
+    }
+
+    /// Picks the type of a float literal the same way, defaulting to `f64`.
+    fn float_literal_vtype(suffix: Option<&ValueType>, hint: Option<&ValueType>) -> ValueType {
+        match (suffix, hint) {
+            (Some(suffix), _) => suffix.clone(),
+            (None, Some(hint)) if hint.is_float() => hint.clone(),
+            _ => ValueType::f64(),
+        }
+    }
+
+    /// Whether `expr` is a number literal whose type comes from context.
+    fn is_untyped_literal(expr: &Expression) -> bool {
+        match &expr.kind {
+            ExprKind::Int { suffix, .. } | ExprKind::Float { suffix, .. } => suffix.is_none(),
+            ExprKind::Unary { op: Token::Minus, right } => Self::is_untyped_literal(right),
+            _ => false,
+        }
+    }
+
+    /// Returns the variable a place is stored in, or the dereference it goes
+    /// through.
+    fn place_root(place: &Expression) -> &Expression {
+        match &place.kind {
+            ExprKind::Field { value, .. } | ExprKind::Index { value, .. } => Self::place_root(value),
+            _ => place,
+        }
+    }
+
+    /// Reports changing a place rooted in a binding declared without `mut`,
+    /// where `action` says how, like "assign to". Places behind a pointer
+    /// can always be changed.
+    fn check_mutable(&mut self, target: &Expression, env: &Environment, action: &str, primary: &str) {
+        let ExprKind::Ident { value: name } = &Self::place_root(target).kind else { return };
+        let Some(symbol) = env.get_symbol(name) else { return };
+        if symbol.mutable {
+            return;
+        }
+
+        let mut diag = Diagnostic::error(target.span.clone(),
+                format!("cannot {} immutable {}", action, name))
+            .with_code("E0016")
+            .with_primary(primary);
+
+        if let ValueType::Func(_) = symbol.vtype {
+            diag = diag.with_note(format!("{name} is a function"));
+        } else if symbol.span.line > 0 {
+            diag = diag.with_label(symbol.span.clone(), format!("help: declare it as `mut {name}`"));
+        }
+
+        self.report(diag);
+    }
+
+    /// Whether `expr` names a storage location that can be assigned to.
+    /// Slices are read-only, and so are the lengths of arrays and slices.
+    fn is_place(expr: &Expression) -> bool {
+        match &expr.kind {
+            ExprKind::Ident { .. } | ExprKind::Unary { op: Token::Asterisk, .. } => true,
+            ExprKind::Field { value, .. } if Self::has_len(&value.vtype) => false,
+            ExprKind::Index { value, .. } if matches!(value.vtype, ValueType::Slice(_)) => false,
+            ExprKind::Field { value, .. } | ExprKind::Index { value, .. } => Self::is_place(value),
+            _ => false,
+        }
+    }
+
+    /// Explains why `is_place` rejected `expr`.
+    fn not_a_place_note(expr: &Expression) -> &'static str {
+        match &expr.kind {
+            ExprKind::Field { value, .. } | ExprKind::Index { value, .. } => match &value.vtype {
+                ValueType::Slice(_) => "slices are read-only views",
+                ValueType::Array(..) if matches!(expr.kind, ExprKind::Field { .. }) => "the length of an array is fixed",
+                vtype if *vtype == ValueType::str() => "strings are read-only",
+                _ => Self::not_a_place_note(value),
+            },
+            _ => "only variables can be assigned to",
+        }
+    }
+
+    /// Returns the variable a pointer or slice made by `value` points into,
+    /// looking inside aggregates. Only direct uses of `&` and slicing are
+    /// seen, not pointers stored in a variable first.
+    fn borrowed_local(value: &Expression) -> Option<&Expression> {
+        let root = match &value.kind {
+            ExprKind::Unary { op: Token::Ampersand, right } => Self::place_root(right),
+            ExprKind::Slice { value, .. } if matches!(value.vtype, ValueType::Array(..)) => Self::place_root(value),
+            ExprKind::StructLit { fields, .. } => return fields.iter().find_map(|(_, value)| Self::borrowed_local(value)),
+            ExprKind::Variant { args: values, .. } | ExprKind::Array { elements: values } =>
+                return values.iter().find_map(Self::borrowed_local),
+            _ => return None,
+        };
+
+        matches!(root.kind, ExprKind::Ident { .. }).then_some(root)
+    }
+
+    /// Reports returning a pointer into a local variable, which no longer
+    /// exists once the function returns.
+    fn check_escaping_local(&mut self, value: &Expression, env: &Environment) {
+        let Some(local) = Self::borrowed_local(value) else { return };
+        let ExprKind::Ident { value: name } = &local.kind else { return };
+
+        let mut diag = Diagnostic::error(value.span.clone(),
+                format!("cannot return a pointer to local variable {}", name))
+            .with_code("E0025")
+            .with_primary("returns a pointer to data owned by the current function")
+            .with_note(format!("{name} no longer exists once the function returns"));
+
+        if let Some(symbol) = env.get_symbol(name) && symbol.span.line > 0 {
+            diag = diag.with_label(symbol.span.clone(), format!("{name} is declared here"));
+        }
+
+        self.report(diag);
+    }
+
+    /// Whether values of `vtype` have a `.len` field.
+    fn has_len(vtype: &ValueType) -> bool {
+        matches!(vtype, ValueType::Array(..) | ValueType::Slice(_)) || *vtype == ValueType::str()
+    }
+
+    /// Checks that an array index or slice bound is an integer.
+    fn expect_index(&mut self, index: &Expression) {
+        if index.vtype != ValueType::Unknown && !index.vtype.is_integer() {
+            self.report(Diagnostic::error(index.span.clone(), "mismatched types")
+                .with_code("E0006")
+                .with_primary(format!("expected an integer, found {}", index.vtype))
+                .with_note("arrays and slices can only be indexed by integers"));
+        }
+    }
+
+    /// Reports constant slice bounds that are reversed, or past the end of an
+    /// array of length `len`.
+    fn check_slice_bounds(&mut self, start: Option<&Expression>, end: Option<&Expression>, len: Option<u64>) {
+        let constant = |bound: &Expression| match bound.kind {
+            ExprKind::Int { value, .. } => Some(value),
+            _ => None,
+        };
+
+        if let (Some(start), Some(end)) = (start, end)
+                && let (Some(lo), Some(hi)) = (constant(start), constant(end))
+                && lo > hi {
+            self.report(Diagnostic::error(start.span.to(&end.span), "slice index starts after it ends")
+                .with_code("E0023")
+                .with_primary(format!("the slice starts at {lo} but ends at {hi}")));
+        }
+
+        for bound in [start, end].into_iter().flatten() {
+            if let (Some(len), Some(value)) = (len, constant(bound)) && value > len {
+                self.report(Diagnostic::error(bound.span.clone(), "index out of bounds")
+                    .with_code("E0023")
+                    .with_primary(format!("the length is {len} but the slice bound is {value}")));
+            }
+        }
+    }
+
+    fn check_int_range(&mut self, value: i128, vtype: &ValueType, span: &Span) {
+        let Some((min, max)) = vtype.int_range() else { return };
+
+        if value < min || value > max {
+            self.report(Diagnostic::error(span.clone(),
+                    format!("literal out of range for {}", vtype))
+                .with_code("E0013")
+                .with_primary(format!("{value} does not fit in {vtype}"))
+                .with_note(format!("the range of {vtype} is {min}..={max}")));
+        }
+    }
+
+    fn check_float_range(&mut self, value: f64, vtype: &ValueType, span: &Span) {
+        if *vtype == ValueType::Type("f32".to_owned()) && value.abs() > f64::from(f32::MAX) {
+            self.report(Diagnostic::error(span.clone(),
+                    format!("literal out of range for {}", vtype))
+                .with_code("E0013")
+                .with_primary(format!("{value:e} does not fit in {vtype}"))
+                .with_note(format!("the largest {vtype} is {:e}", f32::MAX)));
+        }
+    }
+
+    /// Checks a pair of operands that must end up with the same type. An
+    /// untyped literal on either side takes the type of the other side, so
+    /// `x < 1` and `1 < x` both work for any integer `x`.
+    fn check_operands(&mut self, left: &mut Expression, right: &mut Expression, env: &Environment, hint: Option<&ValueType>) {
+        if Self::is_untyped_literal(left) && !Self::is_untyped_literal(right) {
+            self.check_expression_as(right, env, hint);
+            self.check_expression_as(left, env, Some(&right.vtype.clone()));
+        } else {
+            self.check_expression_as(left, env, hint);
+            self.check_expression_as(right, env, Some(&left.vtype.clone()));
+        }
+    }
+
+    /// Checks `expr`, using `hint` as the type the context expects. The hint
+    /// only decides the type of untyped literals; mismatches are still
+    /// reported by the caller.
+    fn check_expression_as(&mut self, expr: &mut Expression, env: &Environment, hint: Option<&ValueType>) {
+        use ExprKind::*;
+
+        expr.vtype = match &mut expr.kind {
+            Ident { value } => match env.get_vtype_of(value) {
+                Some(vtype) => vtype.clone(),
+                None => {
+                    self.report(Diagnostic::error(expr.span.clone(),
+                            format!("{} not found in current scope", value))
+                        .with_code("E0004")
+                        .with_primary("not found in this scope"));
+                    ValueType::Unknown
+                }
+            },
+            Int { value, suffix } => {
+                let vtype = Self::int_literal_vtype(suffix.as_ref(), hint);
+                self.check_int_range(i128::from(*value), &vtype, &expr.span);
+                vtype
+            }
+            Float { value, suffix } => {
+                let vtype = Self::float_literal_vtype(suffix.as_ref(), hint);
+                self.check_float_range(*value, &vtype, &expr.span);
+                vtype
+            }
+            String { .. } => ValueType::str(),
+            Bool { .. } => ValueType::bool(),
+            Unary { op: Token::Ampersand, right } => {
+                let pointee_hint = match hint {
+                    Some(ValueType::Pointer(pointee)) => Some(&**pointee),
+                    _ => None,
+                };
+                self.check_expression_as(right, env, pointee_hint);
+
+                if !Self::is_place(right) {
+                    self.report(Diagnostic::error(right.span.clone(), "cannot take the address of a temporary value")
+                        .with_code("E0024")
+                        .with_primary("this value is not stored anywhere")
+                        .with_note("only variables, their fields and array elements have an address"));
+                } else {
+                    self.check_mutable(right, env, "take a pointer to", "it could be changed through the pointer");
+                }
+
+                match &right.vtype {
+                    ValueType::Unknown => ValueType::Unknown,
+                    vtype => ValueType::Pointer(vtype.clone().into()),
+                }
+            }
+            Unary { op: Token::Asterisk, right } => {
+                self.check_expression(right, env);
+
+                match &right.vtype {
+                    ValueType::Unknown => ValueType::Unknown,
+                    ValueType::Pointer(pointee) => *pointee.clone(),
+                    vtype => {
+                        self.report(Diagnostic::error(expr.span.clone(),
+                                format!("cannot dereference a value of type {}", vtype))
+                            .with_code("E0007")
+                            .with_label(right.span.clone(), format!("this is of type {vtype}")));
+                        ValueType::Unknown
+                    }
+                }
+            }
+            Unary { op, right } => {
+                match (&*op, &mut right.kind) {
+                    // Negative literals are range checked as a whole, so
+                    // that `-128i8` fits.
+                    (Token::Minus, Int { value, suffix }) => {
+                        right.vtype = Self::int_literal_vtype(suffix.as_ref(), hint);
+                        if right.vtype.is_signed() {
+                            self.check_int_range(-i128::from(*value), &right.vtype, &expr.span);
+                        }
+                    }
+                    _ => self.check_expression_as(right, env, hint),
+                }
+
+                match &right.vtype {
+                    ValueType::Unknown => ValueType::Unknown,
+                    vtype => match Self::unary_vtype(op, vtype) {
+                        Some(result) => result,
+                        None => {
+                            self.report(Diagnostic::error(expr.span.clone(),
+                                    format!("{} is not supported for {}", op, vtype))
+                                .with_code("E0007")
+                                .with_label(right.span.clone(), format!("this is of type {vtype}")));
+                            ValueType::Unknown
+                        }
+                    }
+                }
+            }
+            Binary { op, left, right } => {
+                let operand_hint = match op {
+                    Token::Plus | Token::Minus | Token::Asterisk | Token::Slash
+                    | Token::Percent | Token::Ampersand | Token::Pipe | Token::Caret => hint,
+                    _ => None,
+                };
+
+                if let Token::Shl | Token::Shr = op {
+                    // The shifted value is typed by context, the amount on its own.
+                    self.check_expression_as(left, env, hint);
+                    self.check_expression(right, env);
+                } else {
+                    self.check_operands(left, right, env, operand_hint);
+                }
+
+                match (&left.vtype, &right.vtype) {
+                    (ValueType::Unknown, _) | (_, ValueType::Unknown) => ValueType::Unknown,
+                    (left_vtype, right_vtype) => match Self::binary_vtype(op, left_vtype, right_vtype) {
+                        Some(result) => result,
+                        None => {
+                            let mut diag = Diagnostic::error(expr.span.clone(),
+                                    format!("{} is not supported for {} and {}", op, left_vtype, right_vtype))
+                                .with_code("E0007")
+                                .with_label(left.span.clone(), format!("this is of type {left_vtype}"))
+                                .with_label(right.span.clone(), format!("this is of type {right_vtype}"));
+
+                            if left_vtype.is_integer() && right_vtype.is_float()
+                                    || left_vtype.is_float() && right_vtype.is_integer() {
+                                diag = diag.with_note("integers and floats are never converted implicitly");
+                            }
+
+                            self.report(diag);
+                            ValueType::Unknown
+                        }
+                    }
+                }
+            }
+            Assign { op, target, value } => {
+                self.check_expression(target, env);
+                let value_hint = match op {
+                    Some(Token::Shl | Token::Shr) => None,
+                    _ => Some(target.vtype.clone()),
+                };
+                self.check_expression_as(value, env, value_hint.as_ref());
+
+                if !Self::is_place(target) {
+                    self.report(Diagnostic::error(target.span.clone(), "invalid left-hand side of assignment")
+                        .with_code("E0015")
+                        .with_primary("cannot assign to this expression")
+                        .with_note(Self::not_a_place_note(target)));
+                } else {
+                    self.check_mutable(target, env, "assign to", "cannot assign twice");
+                }
+
+                match (op, &target.vtype, &value.vtype) {
+                    (_, ValueType::Unknown, _) | (_, _, ValueType::Unknown) => (),
+                    (None, _, _) => self.expect_vtype(&target.vtype.clone(), value, None),
+                    // The old string can't be freed, since it may be a literal
+                    // or a slice, so appending in place would leak it.
+                    (Some(Token::Plus), target_vtype, _) if *target_vtype == ValueType::str() =>
+                        self.report(Diagnostic::error(expr.span.clone(), "+= is not supported for str")
+                            .with_code("E0007")
+                            .with_primary("would leak the old string")
+                            .with_note("make a new string with `let t = s + x;`, then `free(s)` if it came from +")),
+                    (Some(op), target_vtype, value_vtype) => {
+                        if Self::binary_vtype(op, target_vtype, value_vtype).as_ref() != Some(target_vtype) {
+                            self.report(Diagnostic::error(expr.span.clone(),
+                                    format!("{}= is not supported for {} and {}", op, target_vtype, value_vtype))
+                                .with_code("E0007")
+                                .with_label(target.span.clone(), format!("this is of type {target_vtype}"))
+                                .with_label(value.span.clone(), format!("this is of type {value_vtype}")));
+                        }
+                    }
+                }
+
+                target.vtype.clone()
+            }
+            StructLit { name, fields } => {
+                let decl = env.get_struct(&name.node).cloned();
+                if decl.is_none() {
+                    self.report(Diagnostic::error(name.span.clone(),
+                            format!("struct {} not found in current scope", name.node))
+                        .with_code("E0004")
+                        .with_primary("not found in this scope"));
+                }
+
+                for i in 0..fields.len() {
+                    let (before, rest) = fields.split_at_mut(i);
+                    let (field, value) = &mut rest[0];
+
+                    let Some(decl) = &decl else {
+                        self.check_expression(value, env);
+                        continue;
+                    };
+
+                    let Some(param) = decl.field(&field.node) else {
+                        self.check_expression(value, env);
+                        self.report(Self::no_field(&field.node, &ValueType::Type(decl.name.clone()), &field.span));
+                        continue;
+                    };
+
+                    self.check_expression_as(value, env, Some(&param.vtype));
+                    self.expect_vtype(&param.vtype, value, None);
+
+                    if let Some((prev, _)) = before.iter().find(|(prev, _)| prev.node == field.node) {
+                        self.report(Diagnostic::error(field.span.clone(),
+                                format!("field {} specified more than once", field.node))
+                            .with_code("E0005")
+                            .with_primary("used again here")
+                            .with_label(prev.span.clone(), "first use here"));
+                    }
+                }
+
+                match decl {
+                    Some(decl) => {
+                        let missing: Vec<_> = decl.fields.iter()
+                            .filter(|param| !fields.iter().any(|(field, _)| field.node == param.name))
+                            .map(|param| param.name.as_str())
+                            .collect();
+
+                        if !missing.is_empty() {
+                            self.report(Diagnostic::error(expr.span.clone(),
+                                    format!("missing fields {} in {} literal", missing.join(", "), decl.name))
+                                .with_code("E0019")
+                                .with_primary("every field must be given a value"));
+                        }
+
+                        ValueType::Type(decl.name)
+                    }
+                    None => ValueType::Unknown,
+                }
+            }
+            Variant { enum_name, variant, args } => {
+                let decl = env.get_enum(&enum_name.node);
+                let found = decl.and_then(|decl| decl.variant(&variant.node));
+
+                match (decl, found) {
+                    (None, _) => self.report(Diagnostic::error(enum_name.span.clone(),
+                            format!("enum {} not found in current scope", enum_name.node))
+                        .with_code("E0004")
+                        .with_primary("not found in this scope")),
+                    (Some(decl), None) => self.report(Self::no_variant(&variant.node, &decl.name, &variant.span)),
+                    _ => (),
+                }
+
+                // A variant is checked like a call to a function taking its fields.
+                let constructor = found.map(|found| FuncDecl {
+                    name: format!("{}::{}", enum_name.node, found.name),
+                    vtype: ValueType::Type(enum_name.node.clone()).into(),
+                    params: found.fields.iter().enumerate()
+                        .map(|(i, vtype)| Symbol {
+                            name: i.to_string(),
+                            vtype: vtype.clone(),
+                            span: found.span.clone(),
+                            mutable: false,
+                        })
+                        .collect(),
+                    span: found.span.clone(),
+                });
+
+                for (i, arg) in args.iter_mut().enumerate() {
+                    let hint = constructor.as_ref().and_then(|decl| decl.params.get(i)).map(|param| &param.vtype);
+                    self.check_expression_as(arg, env, hint);
+                }
+
+                match constructor {
+                    Some(constructor) => {
+                        self.check_call(&constructor, args, &expr.span);
+                        *constructor.vtype
+                    }
+                    None => ValueType::Unknown,
+                }
+            }
+            Field { value, field } => {
+                self.check_expression(value, env);
+
+                let decl = match &value.vtype {
+                    ValueType::Type(name) => env.get_struct(name),
+                    _ => None,
+                };
+
+                match (&value.vtype, decl.and_then(|decl| decl.field(&field.node))) {
+                    (ValueType::Unknown, _) => ValueType::Unknown,
+                    (vtype, _) if Self::has_len(vtype) && field.node == "len" => ValueType::usize(),
+                    (_, Some(param)) => param.vtype.clone(),
+                    (vtype, None) => {
+                        self.report(Self::no_field(&field.node, vtype, &field.span));
+                        ValueType::Unknown
+                    }
+                }
+            }
+            Array { elements } => {
+                let mut element_vtype = match hint {
+                    Some(ValueType::Array(element, _)) => Some(*element.clone()),
+                    _ => None,
+                };
+
+                // Elements take the expected element type, or else the type
+                // of the first element.
+                for element in elements.iter_mut() {
+                    self.check_expression_as(element, env, element_vtype.as_ref());
+                    match &element_vtype {
+                        Some(vtype) => self.expect_vtype(&vtype.clone(), element, None),
+                        None => element_vtype = Some(self.infer_vtype(element)),
+                    }
+                }
+
+                match element_vtype {
+                    Some(ValueType::Unknown) => ValueType::Unknown,
+                    Some(element) => ValueType::Array(element.into(), elements.len() as u64),
+                    None => {
+                        self.report(Diagnostic::error(expr.span.clone(), "type annotations needed")
+                            .with_code("E0017")
+                            .with_primary("cannot infer the element type of an empty array"));
+                        ValueType::Unknown
+                    }
+                }
+            }
+            Index { value, index } => {
+                self.check_expression(value, env);
+                self.check_expression_as(index, env, Some(&ValueType::usize()));
+                self.expect_index(index);
+
+                match &value.vtype {
+                    ValueType::Unknown => ValueType::Unknown,
+                    ValueType::Array(element, len) => {
+                        if let Int { value: constant, .. } = index.kind && constant >= *len {
+                            self.report(Diagnostic::error(index.span.clone(), "index out of bounds")
+                                .with_code("E0023")
+                                .with_primary(format!("the length is {len} but the index is {constant}")));
+                        }
+                        *element.clone()
+                    }
+                    ValueType::Slice(element) => *element.clone(),
+                    vtype => {
+                        let mut diag = Diagnostic::error(expr.span.clone(),
+                                format!("cannot index into a value of type {}", vtype))
+                            .with_code("E0007")
+                            .with_label(value.span.clone(), format!("this is of type {vtype}"));
+
+                        if *vtype == ValueType::str() {
+                            diag = diag.with_note("characters can take several bytes; slice the string with s[i..j] instead");
+                        }
+
+                        self.report(diag);
+                        ValueType::Unknown
+                    }
+                }
+            }
+            Slice { value, start, end } => {
+                self.check_expression(value, env);
+                for bound in [&mut *start, &mut *end].into_iter().flatten() {
+                    self.check_expression_as(bound, env, Some(&ValueType::usize()));
+                    self.expect_index(bound);
+                }
+
+                match &value.vtype {
+                    ValueType::Unknown => ValueType::Unknown,
+                    ValueType::Array(element, len) => {
+                        // The slice points into the array, which must outlive it.
+                        if !Self::is_place(value) {
+                            self.report(Diagnostic::error(value.span.clone(), "cannot slice a temporary array")
+                                .with_code("E0024")
+                                .with_primary("this array is dropped while the slice still points to it")
+                                .with_note("store the array in a variable first"));
+                        }
+                        self.check_slice_bounds(start.as_deref(), end.as_deref(), Some(*len));
+                        ValueType::Slice(element.clone())
+                    }
+                    ValueType::Slice(element) => {
+                        self.check_slice_bounds(start.as_deref(), end.as_deref(), None);
+                        ValueType::Slice(element.clone())
+                    }
+                    vtype if *vtype == ValueType::str() => {
+                        let len = match &value.kind {
+                            String { value } => Some(value.len() as u64),
+                            _ => None,
+                        };
+                        self.check_slice_bounds(start.as_deref(), end.as_deref(), len);
+                        ValueType::str()
+                    }
+                    vtype => {
+                        self.report(Diagnostic::error(expr.span.clone(),
+                                format!("cannot slice a value of type {}", vtype))
+                            .with_code("E0007")
+                            .with_label(value.span.clone(), format!("this is of type {vtype}")));
+                        ValueType::Unknown
+                    }
+                }
+            }
+            Cast { value, vtype } => {
+                let target = self.check_vtype_exists(&vtype.node, &vtype.span, env);
+                self.check_expression(value, env);
+
+                match (&value.vtype, &target) {
+                    (ValueType::Unknown, _) | (_, ValueType::Unknown) => target,
+                    (from, to) if Self::can_cast(from, to) => target,
+                    (from, to) => {
+                        self.report(Diagnostic::error(expr.span.clone(),
+                                format!("cannot cast {} to {}", from, to))
+                            .with_code("E0014")
+                            .with_label(value.span.clone(), format!("this is of type {from}"))
+                            .with_note("only numeric types, and bool to integers, can be cast"));
+                        target
+                    }
+                }
+            }
+            Call { func, args } => {
+                self.check_expression(func, env);
+
+                let params: Vec<_> = match (&func.vtype, hint) {
+                    // `alloc` passes the pointee of the expected type on to its value.
+                    (ValueType::Func(decl), Some(ValueType::Pointer(pointee))) if decl.is_builtin("alloc") =>
+                        vec![*pointee.clone()],
+                    (ValueType::Func(decl), _) => decl.params.iter().map(|param| param.vtype.clone()).collect(),
+                    _ => vec![],
+                };
+
+                for (i, arg) in args.iter_mut().enumerate() {
+                    self.check_expression_as(arg, env, params.get(i));
+                }
+
+                match &func.vtype {
+                    ValueType::Unknown => ValueType::Unknown,
+                    ValueType::Func(decl) => {
+                        self.check_call(decl, args, &expr.span);
+                        self.check_allocation(decl, args).unwrap_or_else(|| *decl.vtype.clone())
+                    }
+                    vtype => {
+                        self.report(Diagnostic::error(func.span.clone(),
+                                format!("expression of type {} is not a function", vtype))
+                            .with_code("E0009")
+                            .with_primary("not callable"));
+                        ValueType::Unknown
+                    }
+                }
+            }
+        };
+    }
+
+    /// Types a call to `alloc`, which returns a pointer to a copy of its value
+    /// of any type, or to `free`, which takes any pointer or `str`. Returns
+    /// `None` for other functions.
+    fn check_allocation(&mut self, decl: &FuncDecl, args: &[Expression]) -> Option<ValueType> {
+        let arg = args.first().filter(|arg| arg.vtype != ValueType::Unknown);
+
+        if decl.is_builtin("alloc") {
+            return Some(match arg.map(|arg| self.infer_vtype(arg)) {
+                None | Some(ValueType::Unknown) => ValueType::Unknown,
+                Some(vtype) => ValueType::Pointer(vtype.into()),
+            });
+        }
+
+        if decl.is_builtin("free") {
+            if let Some(arg) = arg && !matches!(arg.vtype, ValueType::Pointer(_)) && arg.vtype != ValueType::str() {
+                self.report(Diagnostic::error(arg.span.clone(), "mismatched types")
+                    .with_code("E0006")
+                    .with_primary(format!("expected a pointer or str, found {}", arg.vtype))
+                    .with_note("only memory from alloc, and strings made by +, can be freed"));
+            } else if let Some(arg) = arg && let Some(what) = Self::never_allocated(arg) {
+                self.report(Diagnostic::error(arg.span.clone(), format!("cannot free {what}"))
+                    .with_code("E0027")
+                    .with_primary("this memory was not allocated")
+                    .with_note("only memory from alloc, and strings made by +, can be freed"));
+            }
+            return Some(ValueType::void());
+        }
+
+        None
+    }
+
+    /// Describes `expr` if it certainly points at memory that `free` must
+    /// not be given: a literal, part of another value, or a variable.
+    fn never_allocated(expr: &Expression) -> Option<&'static str> {
+        match &expr.kind {
+            ExprKind::String { .. } => Some("a string literal"),
+            ExprKind::Slice { .. } => Some("a slice"),
+            ExprKind::Unary { op: Token::Ampersand, right }
+                if matches!(Self::place_root(right).kind, ExprKind::Ident { .. }) => Some("the address of a variable"),
+            _ => None,
+        }
+    }
+
+    fn no_variant(name: &str, enum_name: &str, span: &Span) -> Diagnostic {
+        Diagnostic::error(span.clone(), format!("no variant {} on enum {}", name, enum_name))
+            .with_code("E0021")
+            .with_primary("unknown variant")
+    }
+
+    fn no_field(name: &str, vtype: &ValueType, span: &Span) -> Diagnostic {
+        Diagnostic::error(span.clone(), format!("no field {} on type {}", name, vtype))
+            .with_code("E0018")
+            .with_primary("unknown field")
+    }
+
+    fn check_call(&mut self, decl: &FuncDecl, args: &[Expression], span: &Span) {
+        if args.len() != decl.params.len() {
+            let mut diag = Diagnostic::error(span.clone(),
+                    format!("{} expected {} arguments but got {}",
+                        decl.name, decl.params.len(), args.len()))
+                .with_code("E0008");
+
+            if decl.span.line > 0 {
+                diag = diag.with_label(decl.span.clone(), "function defined here");
+            }
+
+            self.report(diag);
+            return;
+        }
+
+        for (arg, param) in args.iter().zip(decl.params.iter()) {
+            if arg.vtype != ValueType::Unknown && param.vtype != ValueType::Unknown && arg.vtype != param.vtype {
+                self.report(Diagnostic::error(arg.span.clone(), "mismatched types")
+                    .with_code("E0006")
+                    .with_primary(format!("expected {}, found {}", param.vtype, arg.vtype))
+                    .with_note(format!("parameter {} of {} is of type {}",
+                        param.name, decl.name, param.vtype)));
+            }
+        }
+    }
+
+    fn check_statement(&mut self, stmt: &mut Statement, env: &mut Environment) {
+        use StmtKind::*;
+
+        match &mut stmt.kind {
+            Let { name, mutable, vtype, value } => {
+                let declared = match (&vtype.node, value) {
+                    (ValueType::Unknown, Some(value)) => {
+                        self.check_expression(value, env);
+                        vtype.node = self.infer_vtype(value);
+                        vtype.node.clone()
+                    }
+                    (ValueType::Unknown, None) => {
+                        let example = if *mutable {
+                            format!("let mut {}: i32;", name.node)
+                        } else {
+                            format!("let {}: i32 = 0;", name.node)
+                        };
+                        self.report(Diagnostic::error(name.span.clone(), "type annotations needed")
+                            .with_code("E0017")
+                            .with_primary(format!("cannot infer the type of {}", name.node))
+                            .with_note(format!("give it a type, like `{example}`, or an initial value")));
+                        ValueType::Unknown
+                    }
+                    (_, value) => {
+                        // Without `mut` it could never be assigned, so reading it
+                        // would read uninitialized memory.
+                        if value.is_none() && !*mutable {
+                            self.report(Diagnostic::error(name.span.clone(),
+                                    format!("immutable {} is never initialized", name.node))
+                                .with_code("E0016")
+                                .with_primary("needs an initial value")
+                                .with_note(format!("give it a value, or declare it as `let mut {}` to assign it later", name.node)));
+                        }
+
+                        let declared = self.check_vtype_exists(&vtype.node, &vtype.span, env);
+                        if let Some(value) = value {
+                            self.check_expression_as(value, env, Some(&declared));
+                            self.expect_vtype(&declared, value, Some((&vtype.span, "expected due to this")));
+                        }
+                        declared
+                    }
+                };
+
+                // The binding is usable even if its initializer is broken, so
+                // later uses don't report it as missing.
+                self.define(Symbol {
+                    name: name.node.clone(),
+                    vtype: declared,
+                    span: name.span.clone(),
+                    mutable: *mutable,
+                }, env);
+            }
+            Return { value } => {
+                self.check_expression_as(value, env, self.return_vtype.clone().as_ref());
+                self.check_escaping_local(value, env);
+
+                match self.return_vtype.clone() {
+                    Some(vtype) => self.expect_vtype(&vtype, value, None),
+                    None => self.report(Diagnostic::error(stmt.span.clone(),
+                            "return outside of a function")
+                        .with_code("E0010")),
+                }
+            }
+            If { cond, then, else_then } => {
+                self.check_expression(cond, env);
+                self.expect_condition(cond);
+                self.check_block(then, env);
+
+                if let Some(else_then) = else_then {
+                    self.check_statement(else_then, env);
+                }
+            }
+            While { cond, body } => {
+                self.check_expression(cond, env);
+                self.expect_condition(cond);
+                self.check_loop_body(body, env);
+            }
+            For { var, start, end, body } => {
+                self.check_operands(start, end, env, None);
+                self.expect_vtype(&start.vtype, end, None);
+
+                let vtype = match &start.vtype {
+                    vtype if vtype.is_integer() || *vtype == ValueType::Unknown => vtype.clone(),
+                    vtype => {
+                        self.report(Diagnostic::error(start.span.to(&end.span), "mismatched types")
+                            .with_code("E0006")
+                            .with_primary(format!("expected an integer range, found {vtype}")));
+                        ValueType::Unknown
+                    }
+                };
+
+                let mut env = Environment::from_parent(env);
+                self.define(Symbol {
+                    name: var.node.clone(),
+                    vtype,
+                    span: var.span.clone(),
+                    mutable: false,
+                }, &mut env);
+
+                self.check_loop_body(body, &env);
+            }
+            Break | Continue => {
+                if self.loop_depth == 0 {
+                    let keyword = if matches!(stmt.kind, Break) { "break" } else { "continue" };
+                    self.report(Diagnostic::error(stmt.span.clone(),
+                            format!("{keyword} outside of a loop"))
+                        .with_code("E0012")
+                        .with_primary(format!("cannot {keyword} here")));
+                }
+            }
+            Expression { value } => self.check_expression(value, env),
+            Block { body } => self.check_block(body, env),
+            Func { decl, body } => {
+                self.declare_func(decl, env);
+                self.check_func_body(decl, body, env);
+            }
+            Struct { decl } => {
+                self.declare_type(&decl.name, &decl.span, env);
+                self.resolve_struct(decl, env);
+                self.check_type_cycle(&decl.name, &decl.span, env);
+            }
+            Enum { decl } => {
+                self.declare_type(&decl.name, &decl.span, env);
+                self.resolve_enum(decl, env);
+                self.check_type_cycle(&decl.name, &decl.span, env);
+            }
+            Match { value, arms } => self.check_match(value, arms, env),
+        }
+    }
+
+    /// Checks the arms of a `match` against the enum matched on, and that
+    /// every variant is covered by some arm.
+    fn check_match(&mut self, value: &mut Expression, arms: &mut [MatchArm], env: &Environment) {
+        self.check_expression(value, env);
+
+        let decl = match &value.vtype {
+            ValueType::Unknown => None,
+            ValueType::Type(name) if env.get_enum(name).is_some() => env.get_enum(name).cloned(),
+            vtype => {
+                self.report(Diagnostic::error(value.span.clone(), "mismatched types")
+                    .with_code("E0006")
+                    .with_primary(format!("expected an enum, found {vtype}"))
+                    .with_note("only enums can be matched on"));
+                None
+            }
+        };
+
+        let mut covered: Vec<&str> = vec![];
+        let mut wildcard: Option<Span> = None;
+
+        for arm in arms.iter_mut() {
+            let mut env = Environment::from_parent(env);
+            let span = &arm.pattern.span;
+
+            if let Some(prev) = &wildcard {
+                self.report(Diagnostic::warning(span.clone(), "unreachable pattern")
+                    .with_label(prev.clone(), "this matches every value"));
+            }
+
+            match &mut arm.pattern.node {
+                Pattern::Wildcard => {
+                    wildcard.get_or_insert(span.clone());
+                }
+                Pattern::Variant { enum_name, variant, bindings } => {
+                    let mut fields = vec![];
+
+                    match &decl {
+                        Some(decl) if enum_name.node != decl.name => {
+                            self.report(Diagnostic::error(enum_name.span.clone(), "mismatched types")
+                                .with_code("E0006")
+                                .with_primary(format!("expected {}, found {}", decl.name, enum_name.node))
+                                .with_label(value.span.clone(), format!("this is of type {}", decl.name)));
+                        }
+                        Some(decl) => match decl.variant(&variant.node) {
+                            Some(found) => {
+                                if bindings.len() != found.fields.len() {
+                                    self.report(Diagnostic::error(span.clone(),
+                                            format!("{}::{} has {} fields, but the pattern has {}",
+                                                decl.name, found.name, found.fields.len(), bindings.len()))
+                                        .with_code("E0008")
+                                        .with_label(found.span.clone(), "variant defined here"));
+                                }
+
+                                if covered.contains(&found.name.as_str()) && wildcard.is_none() {
+                                    self.report(Diagnostic::warning(span.clone(), "unreachable pattern")
+                                        .with_primary(format!("{}::{} is already matched", decl.name, found.name)));
+                                }
+
+                                covered.push(&found.name);
+                                fields = found.fields.clone();
+                            }
+                            None => self.report(Self::no_variant(&variant.node, &decl.name, &variant.span)),
+                        },
+                        None => (),
+                    }
+
+                    // Bindings that couldn't be typed are still defined, so
+                    // their uses aren't reported as missing.
+                    for (i, binding) in bindings.iter_mut().enumerate() {
+                        binding.vtype = fields.get(i).cloned().unwrap_or(ValueType::Unknown);
+                        if binding.name != "_" {
+                            self.define(binding.clone(), &mut env);
+                        }
+                    }
+                }
+            }
+
+            self.check_statements(&mut arm.body, &mut env);
+        }
+
+        if let Some(decl) = &decl && wildcard.is_none() {
+            let missing: Vec<_> = decl.variants.iter()
+                .filter(|variant| !covered.contains(&variant.name.as_str()))
+                .map(|variant| format!("{}::{}", decl.name, variant.name))
+                .collect();
+
+            if !missing.is_empty() {
+                self.report(Diagnostic::error(value.span.clone(), "non-exhaustive match")
+                    .with_code("E0022")
+                    .with_primary(format!("{} not covered", missing.join(", ")))
+                    .with_note("add an arm for each variant, or a `_` arm"));
+            }
+        }
+    }
+
+    /// Makes `name`, a struct or enum declared at `span`, usable as a type in `env`.
+    fn declare_type(&mut self, name: &str, span: &Span, env: &mut Environment) {
+        if env.does_vtype_exist(name) {
+            let prev = env.get_struct(name).map(|prev| &prev.span)
+                .or_else(|| env.get_enum(name).map(|prev| &prev.span))
+                .map_or(Span::default(), Span::clone);
+            self.report(Environment::redefinition(name, span, &prev));
+            return;
+        }
+
+        env.push_vtype(ValueType::Type(name.to_owned())).unwrap();
+    }
+
+    /// Checks the fields of `decl` and records them in `env`. Runs after every
+    /// struct in scope is declared, so fields may name structs defined later.
+    fn resolve_struct(&mut self, decl: &mut StructDecl, env: &mut Environment) {
+        for i in 0..decl.fields.len() {
+            let field = &decl.fields[i];
+            if let Some(prev) = decl.fields[..i].iter().find(|prev| prev.name == field.name) {
+                self.report(Environment::redefinition(&field.name, &field.span, &prev.span));
+            }
+
+            let vtype = self.check_vtype_exists(&field.vtype, &field.span, env);
+            decl.fields[i].vtype = vtype;
+        }
+
+        // Only the first declaration of a name is recorded.
+        if env.get_struct(&decl.name).is_none() && env.get_enum(&decl.name).is_none() {
+            env.push_struct(decl.clone());
+        }
+    }
+
+    /// Checks the variants of `decl` and records them in `env`, like
+    /// `resolve_struct`.
+    fn resolve_enum(&mut self, decl: &mut EnumDecl, env: &mut Environment) {
+        if decl.variants.is_empty() {
+            self.report(Diagnostic::error(decl.span.clone(), format!("enum {} has no variants", decl.name))
+                .with_code("E0021")
+                .with_primary("needs at least one variant")
+                .with_note("a value of it could never be made"));
+        }
+
+        for i in 0..decl.variants.len() {
+            let variant = &decl.variants[i];
+            if let Some(prev) = decl.variants[..i].iter().find(|prev| prev.name == variant.name) {
+                self.report(Environment::redefinition(&variant.name, &variant.span, &prev.span));
+            }
+
+            let span = variant.span.clone();
+            for field in decl.variants[i].fields.iter_mut() {
+                *field = self.check_vtype_exists(field, &span, env);
+            }
+        }
+
+        if env.get_struct(&decl.name).is_none() && env.get_enum(&decl.name).is_none() {
+            env.push_enum(decl.clone());
+        }
+    }
+
+    /// Reports a struct or enum that contains itself by value, directly or
+    /// through other types, since it would have infinite size.
+    fn check_type_cycle(&mut self, name: &str, span: &Span, env: &Environment) {
+        fn contains(env: &Environment, outer: &str, target: &str, seen: &mut Vec<String>) -> bool {
+            env.member_vtypes(outer).into_iter().any(|vtype| match vtype.element_base() {
+                ValueType::Type(name) if name == target => true,
+                ValueType::Type(name) if !seen.contains(name) => {
+                    seen.push(name.clone());
+                    contains(env, name, target, seen)
+                }
+                _ => false,
+            })
+        }
+
+        if contains(env, name, name, &mut vec![]) {
+            self.report(Diagnostic::error(span.clone(),
+                    format!("recursive type {} has infinite size", name))
+                .with_code("E0020")
+                .with_primary("contains itself by value"));
+        }
+    }
+
+    /// Returns the type a `let` without an annotation takes from `value`,
+    /// reporting values no variable can hold.
+    fn infer_vtype(&mut self, value: &Expression) -> ValueType {
+        match &value.vtype {
+            ValueType::Type(name) if *name == "void" => {
+                self.report(Diagnostic::error(value.span.clone(), "type annotations needed")
+                    .with_code("E0017")
+                    .with_primary("this expression has no value"));
+                ValueType::Unknown
+            }
+            ValueType::Func(decl) => {
+                self.report(Diagnostic::error(value.span.clone(), "type annotations needed")
+                    .with_code("E0017")
+                    .with_primary(format!("{} is a function", decl.name))
+                    .with_note("functions can only be called, not stored"));
+                ValueType::Unknown
+            }
+            vtype => vtype.clone(),
+        }
+    }
+
+    /// Resolves the signature of `decl` and makes it callable in `env`.
+    fn declare_func(&mut self, decl: &mut FuncDecl, env: &mut Environment) {
+        decl.vtype = self.check_vtype_exists(&decl.vtype, &decl.span, env).into();
+        for param in decl.params.iter_mut() {
+            param.vtype = self.check_vtype_exists(&param.vtype, &param.span, env);
+        }
+
+        self.define(Symbol {
+            name: decl.name.clone(),
+            vtype: ValueType::Func(decl.clone()),
+            span: decl.span.clone(),
+            mutable: false,
+        }, env);
+    }
+
+    fn check_func_body(&mut self, decl: &FuncDecl, body: &mut BlockStmt, env: &Environment) {
+        let mut env = Environment::from_parent(env);
+
+        for param in &decl.params {
+            self.define(param.clone(), &mut env);
+        }
+
+        let outer = self.return_vtype.replace(*decl.vtype.clone());
+        let outer_loops = std::mem::take(&mut self.loop_depth);
+        self.check_statements(body, &mut env);
+        let returns = self.check_flow(body) == Flow::Returns;
+        self.return_vtype = outer;
+        self.loop_depth = outer_loops;
+
+        if !returns && ![ValueType::void(), ValueType::Unknown].contains(&decl.vtype) {
+            self.report(Diagnostic::error(decl.span.clone(),
+                    format!("{} may finish without returning a value", decl.name))
+                .with_code("E0011")
+                .with_primary(format!("expected {} to be returned on every path", decl.vtype))
+                .with_note("add a return statement at the end of the function"));
+        }
+    }
+
+    /// Returns how control leaves `stmts`, warning about any statement that
+    /// follows an unconditional `return`, `break` or `continue`. Nested blocks
+    /// are visited even when they are optional, so their dead code is
+    /// reported too.
+    fn check_flow(&mut self, stmts: &[Statement]) -> Flow {
+        let mut exit: Option<(&Statement, Flow)> = None;
+
+        for stmt in stmts {
+            if let Some((jump, _)) = exit {
+                self.report(Diagnostic::warning(stmt.span.clone(), "unreachable statement")
+                    .with_primary("this statement is never executed")
+                    .with_label(jump.span.clone(), "any code following this is unreachable"));
+                break;
+            }
+
+            let flow = match &stmt.kind {
+                StmtKind::Return { .. } => Flow::Returns,
+                // A stray break was already reported; don't also report the
+                // code after it.
+                StmtKind::Break | StmtKind::Continue if self.loop_depth > 0 => Flow::Jumps,
+                StmtKind::Break | StmtKind::Continue => Flow::Continues,
+                StmtKind::Block { body } => self.check_flow(body),
+                StmtKind::If { then, else_then, .. } => {
+                    let then = self.check_flow(then);
+                    let else_then = else_then.as_ref()
+                        .map_or(Flow::Continues, |else_then| self.check_flow(std::slice::from_ref(else_then)));
+
+                    match (then, else_then) {
+                        (Flow::Returns, Flow::Returns) => Flow::Returns,
+                        (Flow::Continues, _) | (_, Flow::Continues) => Flow::Continues,
+                        _ => Flow::Jumps,
+                    }
+                }
+                // A loop body may run zero times, and a break inside it only
+                // leaves the loop.
+                StmtKind::While { body, .. } | StmtKind::For { body, .. } => {
+                    self.loop_depth += 1;
+                    self.check_flow(body);
+                    self.loop_depth -= 1;
+                    Flow::Continues
+                }
+                StmtKind::Let { .. } | StmtKind::Expression { .. } | StmtKind::Func { .. }
+                | StmtKind::Struct { .. } | StmtKind::Enum { .. } => Flow::Continues,
+                StmtKind::Match { arms, .. } => {
+                    let flows: Vec<_> = arms.iter().map(|arm| self.check_flow(&arm.body)).collect();
+
+                    if flows.is_empty() || flows.contains(&Flow::Continues) {
+                        Flow::Continues
+                    } else if flows.iter().all(|flow| *flow == Flow::Returns) {
+                        Flow::Returns
+                    } else {
+                        Flow::Jumps
+                    }
+                }
+            };
+
+            if flow != Flow::Continues {
+                exit = Some((stmt, flow));
+            }
+        }
+
+        exit.map_or(Flow::Continues, |(_, flow)| flow)
+    }
+
+    fn check_block(&mut self, block: &mut BlockStmt, env: &Environment) {
+        let mut env = Environment::from_parent(env);
+        self.check_statements(block, &mut env);
+    }
+
+    fn check_loop_body(&mut self, block: &mut BlockStmt, env: &Environment) {
+        self.loop_depth += 1;
+        self.check_block(block, env);
+        self.loop_depth -= 1;
+    }
+
+    fn check_statements(&mut self, stmts: &mut [Statement], env: &mut Environment) {
+        for stmt in stmts {
+            self.check_statement(stmt, env);
+        }
+    }
+
+    /// Checks the whole program. Top-level structs and functions are declared
+    /// before any body is checked, so they can be used regardless of order.
+    ///
+    /// On success the warnings found along the way are returned; on failure
+    /// every diagnostic is, warnings included.
+    pub fn check_program(&mut self, program: &mut Program) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+        let mut env = Environment::root();
+
+        for stmt in program.body.iter() {
+            match &stmt.kind {
+                StmtKind::Struct { decl } => self.declare_type(&decl.name, &decl.span, &mut env),
+                StmtKind::Enum { decl } => self.declare_type(&decl.name, &decl.span, &mut env),
+                _ => (),
+            }
+        }
+
+        for stmt in program.body.iter_mut() {
+            match &mut stmt.kind {
+                StmtKind::Struct { decl } => self.resolve_struct(decl, &mut env),
+                StmtKind::Enum { decl } => self.resolve_enum(decl, &mut env),
+                _ => (),
+            }
+        }
+
+        for stmt in program.body.iter_mut() {
+            match &mut stmt.kind {
+                StmtKind::Struct { decl } => self.check_type_cycle(&decl.name, &decl.span, &env),
+                StmtKind::Enum { decl } => self.check_type_cycle(&decl.name, &decl.span, &env),
+                StmtKind::Func { decl, .. } => self.declare_func(decl, &mut env),
+                _ => (),
+            }
+        }
+
+        for stmt in program.body.iter_mut() {
+            match &mut stmt.kind {
+                StmtKind::Func { decl, body } => self.check_func_body(decl, body, &env),
+                StmtKind::Struct { .. } | StmtKind::Enum { .. } => (),
+                _ => self.check_statement(stmt, &mut env),
+            }
+        }
+
+        if self.diagnostics.iter().any(Diagnostic::is_error) {
+            return Err(std::mem::take(&mut self.diagnostics));
+        }
+
+        Ok(std::mem::take(&mut self.diagnostics))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn check(input: &str) -> Result<Program, Vec<Diagnostic>> {
+        let lexer = Lexer::from_file("test.sxl", input.as_bytes().to_vec());
+        let mut program = Parser::new(lexer).unwrap().parse_program()?;
+        TypeChecker::new().check_program(&mut program)?;
+        Ok(program)
+    }
+
+    fn error_lines(input: &str) -> Vec<usize> {
+        check(input).err().unwrap().iter()
+            .map(|diag| diag.primary.span.line)
+            .collect()
+    }
+
+    #[test]
+    fn test_fills_vtypes() {
+        let program = check("fn add(a: i32, b: i32) -> i32 {
+    return a + b;
+}
+let s: str = \"hi\";").unwrap();
+
+        let StmtKind::Func { body, .. } = &program.body[0].kind else { panic!() };
+        let StmtKind::Return { value } = &body[0].kind else { panic!() };
+        assert_eq!(value.vtype, ValueType::i32());
+    }
+
+    #[test]
+    fn test_reports_all_errors() {
+        let lines = error_lines("fn main() -> i32 {
+    let a: i32 = \"x\";
+    let b: foo = 1;
+    printf(a);
+    return c;
+}");
+        assert_eq!(lines, vec![2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_forward_references() {
+        check("fn is_even(n: i32) -> i32 {
+    if n == 0 { return 1; }
+    return is_odd(n - 1);
+}
+
+fn is_odd(n: i32) -> i32 {
+    if n == 0 { return 0; }
+    return is_even(n - 1);
+}").unwrap();
+
+        let lines = error_lines("fn a() -> i32 { return 1; }
+fn a() -> i32 { return 2; }");
+        assert_eq!(lines, vec![2]);
+    }
+
+    #[test]
+    fn test_missing_return() {
+        let lines = error_lines("fn f(n: i32) -> i32 {
+    if n == 0 {
+        return 1;
+    }
+}
+
+fn g(n: i32) -> i32 {
+    if n == 0 {
+        return 1;
+    } else {
+        { return 2; }
+    }
+}");
+        assert_eq!(lines, vec![1]);
+    }
+
+    #[test]
+    fn test_unreachable_warning() {
+        let lexer = Lexer::new(b"fn f() -> i32 {
+    return 1;
+    printf(\"never\");
+    return 2;
+}".to_vec());
+        let mut program = Parser::new(lexer).unwrap().parse_program().unwrap();
+        let warnings = TypeChecker::new().check_program(&mut program).unwrap();
+
+        assert_eq!(warnings.len(), 1);
+        assert!(!warnings[0].is_error());
+        assert_eq!(warnings[0].primary.span.line, 3);
+    }
+
+    #[test]
+    fn test_loops() {
+        check("fn sum(n: i32) -> i32 {
+    let mut total: i32 = 0;
+    for i in 0..n {
+        if i == 3 { continue; }
+        total = total + i;
+    }
+    while total > 100 {
+        total = total - 100;
+        if total == 0 { break; }
+    }
+    return total;
+}").unwrap();
+
+        let lines = error_lines("fn f() -> i32 {
+    if true { break; }
+    while true {
+        fn g() -> i32 { if false { continue; } return 1; }
+    }
+    return 0;
+}");
+        assert_eq!(lines, vec![2, 4]);
+
+        // Only the stray break is reported, not the code after it.
+        let errs = check("fn f() -> i32 { break; return 0; }").err().unwrap();
+        assert_eq!(errs.len(), 1);
+        assert_eq!(errs[0].code, Some("E0012"));
+    }
+
+    #[test]
+    fn test_bool() {
+        check("fn f(a: i32, b: bool) -> bool {
+    if a < 3 && !b || a == 10 {
+        return true;
+    }
+    return b == false;
+}").unwrap();
+
+        let lines = error_lines("fn f(a: i32, b: bool) -> bool {
+    if a { return true; }
+    while a + 1 { }
+    let c: bool = !a;
+    let d: bool = b && a > 1 || a;
+    return -b;
+}");
+        assert_eq!(lines, vec![2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_else_if() {
+        check("fn sign(n: i32) -> i32 {
+    if n > 0 {
+        return 1;
+    } else if n == 0 {
+        return 0;
+    } else {
+        return -1;
+    }
+}").unwrap();
+
+        let lines = error_lines("fn sign(n: i32) -> i32 {
+    if n > 0 {
+        return 1;
+    } else if n {
+        return 0;
+    }
+}");
+        assert_eq!(lines, vec![4, 1]);
+    }
+
+    #[test]
+    fn test_integer_types() {
+        check("fn f(a: u8, b: i64, c: usize) -> i64 {
+    let x: u8 = a + 200;
+    let y: i8 = -128;
+    let z: u64 = 18446744073709551615;
+    if 1 < a && c > 0 {
+        return b * 5_000_000_000;
+    }
+    for i in 0..c { }
+    return f(0xff, -1, 3) + 0x7fffffffffffffffi64;
+}").unwrap();
+
+        let lines = error_lines("fn f(a: u8, b: i32) -> i32 {
+    let x: u8 = 256;
+    let y: i8 = -129;
+    let z: u32 = -1;
+    let w: i32 = a + b;
+    let v: u8 = -a;
+    let u: i64 = 1i32;
+    return 3000000000;
+}");
+        assert_eq!(lines, vec![2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_float_types() {
+        check("fn area(r: f32) -> f32 {
+    let pi: f32 = 3.14159;
+    return pi * r * r;
+}
+fn f(x: f64) -> f64 {
+    let y: f32 = -area(2.5) / 1.0f32;
+    if x < 0.0 || -x > 1e300 {
+        return -1.5 / x;
+    }
+    return x * 0.5;
+}").unwrap();
+
+        let lines = error_lines("fn f(a: i32, x: f64) -> f64 {
+    let y: f64 = a + x;
+    let z: f64 = x * 2;
+    let w: i32 = 1.5;
+    let v: f32 = 1e39;
+    let u: f32 = x;
+    return 1.0f32;
+}");
+        assert_eq!(lines, vec![2, 3, 4, 5, 6, 7]);
+
+        let errs = check("fn f(a: i32, x: f64) -> f64 {
+    return a + x;
+}").err().unwrap();
+        assert_eq!(errs[0].notes, vec!["integers and floats are never converted implicitly"]);
+    }
+
+    #[test]
+    fn test_bitwise() {
+        check("fn f(a: u32, b: u8, n: i64) -> u32 {
+    let m: u64 = 1 << b;
+    let x: u32 = (a & 0xff) | a ^ ~a >> b % 8;
+    let r: i64 = n % 7 << 2;
+    return a << 3 & 0xf0;
+}").unwrap();
+
+        let lines = error_lines("fn f(a: u32, b: u8, x: f64, t: bool) -> u32 {
+    let y: u32 = a & b;
+    let z: f64 = x % 2.0;
+    let w: f64 = ~x;
+    let v: bool = t | t;
+    let u: u32 = a << x;
+    return a & 1 == 1;
+}");
+        assert_eq!(lines, vec![2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_assignment() {
+        check("fn f(mut a: i32, mut b: u8, mut x: f64) -> i32 {
+    a = 1;
+    a += 2;
+    a %= 3;
+    a <<= b;
+    b |= 0x80;
+    x /= 2.0;
+    a = a = 4;
+    return a;
+}").unwrap();
+
+        let lines = error_lines("fn f(mut a: i32, b: u8, mut x: f64) -> i32 {
+    1 + 2 = 3;
+    f(a, b, x) = 1;
+    a = b;
+    a += x;
+    x %= 2.0;
+    return a;
+}");
+        assert_eq!(lines, vec![2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_mutability() {
+        check("fn f(mut n: i32) -> i32 {
+    let mut a: i32 = 1;
+    a += n;
+    n = a;
+    return n;
+}").unwrap();
+
+        let errs = check("fn f(n: i32) -> i32 {
+    let a: i32 = 1;
+    a = 2;
+    n += 1;
+    for i in 0..n { i = 0; }
+    f = f;
+    let b: i32;
+    return a + b;
+}").err().unwrap();
+        let lines: Vec<_> = errs.iter().map(|diag| diag.primary.span.line).collect();
+        assert_eq!(lines, vec![3, 4, 5, 6, 7]);
+        assert!(errs.iter().all(|diag| diag.code == Some("E0016")));
+        assert_eq!(errs[0].secondary[0].span.line, 2);
+    }
+
+    #[test]
+    fn test_let_inference() {
+        let program = check("fn f(a: u8) -> u8 {
+    let x = a + 1;
+    let y = 2.5;
+    let z = 7;
+    let mut b = x < 3;
+    b = true;
+    return x;
+}").unwrap();
+
+        let StmtKind::Func { body, .. } = &program.body[0].kind else { panic!() };
+        let vtypes: Vec<_> = body.iter()
+            .filter_map(|stmt| match &stmt.kind {
+                StmtKind::Let { vtype, .. } => Some(vtype.node.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vtypes, vec!["u8", "f64", "i32", "bool"]);
+
+        let lines = error_lines("fn f() -> i32 {
+    let a;
+    let b = printf(\"hi\");
+    let c = f;
+    let d = missing;
+    let e: i64 = a;
+    return 0;
+}");
+        assert_eq!(lines, vec![2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_structs() {
+        check("fn f(mut p: Point) -> i32 {
+    let line = Line { to: p, from: Point { x: 0, y: 0 } };
+    p.x = line.to.y + 1;
+    p.y += 2;
+    return p.x;
+}
+struct Line { from: Point, to: Point }
+struct Point { x: i32, y: i32 }").unwrap();
+
+        let lines = error_lines("struct Point { x: i32, y: i32, x: u8 }
+struct Bad { inner: Missing }
+struct Outer { inner: Inner }
+struct Inner { outer: Outer }
+fn f(p: Point) -> i32 {
+    let a = Point { x: 1, z: 2, y: 3 };
+    let b = Point { x: 1 };
+    let c = Point { x: 1, y: 2, x: 3 };
+    let d = Nope { x: 1 };
+    let e: i32 = p.z + p.x.y;
+    p.x = 5;
+    return Point { x: 1, y: true }.x;
+}
+struct Point { x: i32 }");
+        assert_eq!(lines, vec![14, 1, 2, 3, 4, 6, 7, 8, 9, 10, 10, 11, 12]);
+    }
+
+    #[test]
+    fn test_enums() {
+        check("enum Shape { Circle(i32), Rect(i32, i32), Empty }
+fn area(s: Shape) -> i32 {
+    match s {
+        Shape::Circle(r) => { return 3 * r * r; }
+        Shape::Rect(w, _) => { return w; }
+        Shape::Empty => { return 0; }
+    }
+}
+fn f() -> i32 {
+    match Shape::Rect(1, 2) {
+        Shape::Circle(r) => { }
+        _ => { return area(Shape::Empty); }
+    }
+    return 1;
+}").unwrap();
+
+        let lines = error_lines("enum Shape { Circle(i32), Rect(i32, i32), Circle, Bad(Missing) }
+enum Other { A }
+enum Rec { Leaf, Node(Rec) }
+fn f(s: Shape, n: i32) -> i32 {
+    let a = Shape::Circle(true);
+    let b = Shape::Rect(1);
+    let c = Shape::Square(1);
+    let d = Nope::A;
+    match n { _ => { } }
+    match s {
+        Other::A => { }
+        Shape::Rect(w) => { return w; }
+        Shape::Triangle => { }
+    }
+}");
+        assert_eq!(lines, vec![1, 1, 3, 5, 6, 7, 8, 9, 11, 12, 13, 10, 4]);
+
+        let lines = error_lines("fn f(n: Never) -> i32 { return 0; }
+enum Never { }");
+        assert_eq!(lines, vec![2]);
+    }
+
+    #[test]
+    fn test_match_warnings() {
+        let warnings = TypeChecker::new().check_program(&mut Parser::new(Lexer::new(b"enum E { A, B }
+fn f(e: E) -> i32 {
+    match e {
+        E::A => { }
+        E::A => { }
+        _ => { }
+        E::B => { }
+    }
+    return 0;
+}".to_vec())).unwrap().parse_program().unwrap()).unwrap();
+
+        let lines: Vec<_> = warnings.iter().map(|diag| diag.primary.span.line).collect();
+        assert_eq!(lines, vec![5, 7]);
+    }
+
+    #[test]
+    fn test_casts() {
+        check("fn f(a: i64, x: f32, b: bool) -> u8 {
+    let y: f64 = a as f64 + x as f64;
+    let i: i32 = b as i32 + y as i32;
+    let w: i64 = -a as i64 * 2;
+    return a as u8;
+}").unwrap();
+
+        let lines = error_lines("fn f(a: i32, s: str, b: bool) -> i32 {
+    let x: i32 = s as i32;
+    let y: f64 = b as f64;
+    let z: bool = a as bool;
+    let w: i32 = a as i33;
+    return a as i64;
+}");
+        assert_eq!(lines, vec![2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_arrays() {
+        let program = check("fn f(mut grid: [[i32; 3]; 2], i: u8) -> i32 {
+    let row = [1, 2, 3];
+    let bytes: [u8; 2] = [0, 255];
+    grid[1] = row;
+    grid[0][i] += row[2];
+    return grid[1][i as usize] + bytes[1] as i32;
+}").unwrap();
+        let StmtKind::Func { body, .. } = &program.body[0].kind else { panic!() };
+        let StmtKind::Let { vtype, .. } = &body[0].kind else { panic!() };
+        assert_eq!(vtype.node.to_string(), "[i32; 3]");
+
+        let lines = error_lines("fn f(a: [i32; 3], b: bool) -> i32 {
+    let x = [];
+    let y: [u8; 2] = [1, 2, 3];
+    let z = [1, true];
+    a[1] = 2;
+    let w = b[0];
+    let v = a[b];
+    let u: [Missing; 2] = [1, 2];
+    return a[3];
+}");
+        assert_eq!(lines, vec![2, 3, 4, 5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn test_slices() {
+        check("fn sum(xs: []i32) -> i32 {
+    let mut total = 0;
+    for i in 0..xs.len {
+        total += xs[i];
+    }
+    return total;
+}
+fn f(a: [i32; 4], n: usize) -> i32 {
+    let all: []i32 = a[..];
+    return sum(a[1..n]) + sum(all[..2]) + a.len as i32;
+}").unwrap();
+
+        let lines = error_lines("fn make() -> [i32; 3] { return [1, 2, 3]; }
+fn f(mut a: [i32; 3], mut s: []i32, b: bool) -> i32 {
+    s[0] = 1;
+    a.len = 2;
+    let t = make()[..];
+    let u = a[2..1];
+    let v = a[..4];
+    let w = b[..];
+    let x = s[b..];
+    let y: []u8 = a[..];
+    return s.size;
+}");
+        assert_eq!(lines, vec![3, 4, 5, 6, 7, 8, 9, 10, 11]);
+    }
+
+    #[test]
+    fn test_strings() {
+        check("fn f(a: str, b: str) -> bool {
+    let s = a + b;
+    let t = s + \"!\";
+    free(s);
+    let head: str = t[..3];
+    let n: usize = t.len + head[1..].len;
+    free(t);
+    return a < b || a == \"x\" && n >= 2;
+}").unwrap();
+
+        let lines = error_lines("fn f(s: str, mut t: str) -> i32 {
+    let a = s + 1;
+    let b = s - t;
+    let c = s[0];
+    s.len = 2;
+    t[0] = \"a\";
+    let d = \"abc\"[2..1];
+    let e = \"abc\"[..4];
+    let g = s == 1;
+    t += s;
+    return 0;
+}");
+        assert_eq!(lines, vec![2, 3, 4, 5, 6, 7, 8, 9, 10]);
+    }
+
+    #[test]
+    fn test_pointers() {
+        check("struct Node { value: i32, next: *Node }
+fn bump(p: *i32) -> i32 {
+    *p += 1;
+    return *p;
+}
+fn last(node: *Node) -> *i32 {
+    return &(*(*node).next).value;
+}
+fn f(mut n: i32, mut node: Node) -> i32 {
+    let mut p = &n;
+    let pp: **i32 = &p;
+    **pp = bump(&node.value);
+    return *last(&node) + *p;
+}").unwrap();
+
+        let lines = error_lines("struct Holder { p: *i32 }
+fn a(mut x: i32) -> *i32 { return &x; }
+fn b() -> []i32 { let xs = [1, 2]; return xs[..]; }
+fn c(mut x: i32) -> Holder { return Holder { p: &x }; }
+fn d(p: *i32) -> *i32 { return &*p; }
+fn e(n: i32) -> i32 {
+    let p = &n;
+    let q = &5;
+    let r = *n;
+    return *p;
+}");
+        assert_eq!(lines, vec![2, 3, 4, 7, 8, 9]);
+    }
+
+    #[test]
+    fn test_allocation() {
+        let program = check("struct Point { x: i32, y: i32 }
+fn f() -> *Point {
+    let n: *u8 = alloc(200);
+    let xs = alloc([1, 2]);
+    free(n);
+    free(xs);
+    return alloc(Point { x: 1, y: 2 });
+}").unwrap();
+        let StmtKind::Func { body, .. } = &program.body[1].kind else { panic!() };
+        let StmtKind::Let { vtype, .. } = &body[1].kind else { panic!() };
+        assert_eq!(vtype.node.to_string(), "*[i32; 2]");
+
+        let lines = error_lines("fn f(n: i32) -> i32 {
+    free(n);
+    let a = alloc();
+    let b = alloc(free);
+    let c: *u8 = alloc(1000);
+    let e: *i32 = alloc(1u8);
+    return 0;
+}
+fn free(x: i32) -> i32 { return x; }");
+        assert_eq!(lines, vec![9, 2, 3, 4, 5, 6]);
+
+        let errs = check("fn f(s: str, p: *i32) -> i32 {
+    let mut n = 1;
+    free(\"x\");
+    free(s[1..]);
+    free(&n);
+    free(&*p);
+    return 0;
+}").err().unwrap();
+        let lines: Vec<_> = errs.iter().map(|diag| diag.primary.span.line).collect();
+        assert_eq!(lines, vec![3, 4, 5]);
+        assert!(errs.iter().all(|diag| diag.code == Some("E0027")));
+    }
+
+    #[test]
+    fn test_unknown_does_not_cascade() {
+        let lines = error_lines("fn main() -> i32 {
+    let a: i32 = missing;
+    let b: i32 = a + missing * 2;
+    return b;
+}");
+        assert_eq!(lines, vec![2, 3]);
+    }
+}